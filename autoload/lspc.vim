@@ -42,6 +42,12 @@ function! lspc#started() abort
   return exists('s:job_id')
 endfunction
 
+" A language can be configured with one server or a list of servers
+function! s:configs(lang_id) abort
+  let l:configs = get(get(g:, 'lspc', {}), a:lang_id, [])
+  return type(l:configs) == v:t_list ? l:configs : [l:configs]
+endfunction
+
 function! lspc#destroy()
   call jobstop(s:job_id)
   unlet! s:job_id
@@ -49,9 +55,18 @@ endfunction
 
 function! lspc#start_lang_server()
   let l:lang_id = 'rust'
-  let l:config = g:lspc[l:lang_id]
   let l:cur_path = lspc#buffer#filename()
-  call rpcnotify(s:job_id, 'start_lang_server', l:lang_id, l:config, l:cur_path)
+  for l:config in s:configs(l:lang_id)
+    call rpcnotify(s:job_id, 'start_lang_server', l:lang_id, l:config, l:cur_path)
+  endfor
+endfunction
+
+" Push updated `settings` of the configured servers
+function! lspc#did_change_configuration()
+  let l:lang_id = 'rust'
+  for l:config in s:configs(l:lang_id)
+    call rpcnotify(s:job_id, 'did_change_configuration', l:lang_id, l:config)
+  endfor
 endfunction
 
 function! lspc#hover()
@@ -61,11 +76,280 @@ function! lspc#hover()
   call rpcnotify(s:job_id, 'hover', l:lang_id, l:cur_path, l:position)
 endfunction
 
+function! lspc#signature_help()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'signature_help', l:lang_id, l:cur_path, l:position)
+endfunction
+
+" Characters which request a feature when typed, by lang_id and feature.
+" Set by servers on initialization
+let s:trigger_characters = {}
+
+function! lspc#set_trigger_characters(lang_id, feature, chars)
+  let l:features = get(s:trigger_characters, a:lang_id, {})
+  let l:chars = get(l:features, a:feature, [])
+  for l:char in a:chars
+    if index(l:chars, l:char) == -1
+      call add(l:chars, l:char)
+    endif
+  endfor
+  let l:features[a:feature] = l:chars
+  let s:trigger_characters[a:lang_id] = l:features
+endfunction
+
+" Commands servers can execute, set on initialization
+let s:server_commands = {}
+
+function! lspc#set_server_commands(lang_id, commands)
+  let l:commands = get(s:server_commands, a:lang_id, [])
+  let s:server_commands[a:lang_id] = uniq(sort(l:commands + a:commands))
+endfunction
+
+function! lspc#complete_server_commands(arglead, cmdline, cursorpos)
+  " Only the command name is completed, not its arguments
+  if a:cmdline[: a:cursorpos - 1] =~# '\s\S\+\s'
+    return []
+  endif
+  let l:commands = get(s:server_commands, 'rust', [])
+  return filter(copy(l:commands), 'stridx(v:val, a:arglead) == 0')
+endfunction
+
+" `args` is the command name followed by its arguments in JSON
+function! lspc#execute_command(args)
+  let l:matches = matchlist(a:args, '^\s*\(\S\+\)\s*\(.*\)$')
+  if empty(l:matches)
+    return
+  endif
+  " The server would never be sent a command with invalid arguments
+  if l:matches[2] !~# '^\s*$'
+    try
+      call json_decode(l:matches[2])
+    catch
+      echoerr '[LSPC] Arguments of ' . l:matches[1] . ' are not valid JSON: ' . l:matches[2]
+      return
+    endtry
+  endif
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  call rpcnotify(s:job_id, 'execute_command', l:lang_id, l:cur_path, l:matches[1], l:matches[2])
+endfunction
+
+function! s:is_trigger_character(lang_id, feature, char)
+  let l:chars = get(get(s:trigger_characters, a:lang_id, {}), a:feature, [])
+  return index(l:chars, a:char) != -1
+endfunction
+
+function! lspc#on_text_changed_i()
+  let l:lang_id = 'rust'
+  let l:char = getline('.')[col('.') - 2]
+  if s:is_trigger_character(l:lang_id, 'signature_help', l:char)
+        \ || (lspc#command#signature_help_visible()
+        \     && s:is_trigger_character(l:lang_id, 'signature_help_retrigger', l:char))
+    call lspc#signature_help()
+  endif
+  if s:is_trigger_character(l:lang_id, 'on_type_formatting', l:char)
+    call lspc#format_on_type(l:char)
+  endif
+endfunction
+
+function! lspc#document_highlight()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'document_highlight', l:lang_id, l:cur_path, l:position)
+endfunction
+
+function! lspc#clear_document_highlight()
+  let l:ns_id = nvim_create_namespace('lspc_document_highlight_' . bufnr())
+  call nvim_buf_clear_namespace(0, l:ns_id, 0, -1)
+endfunction
+
 function! lspc#did_open()
   let l:lang_id = 'rust'
   let l:buf_id = bufnr()
   let l:cur_path = lspc#buffer#filename()
-  call rpcnotify(s:job_id, 'did_open', buf_id, l:cur_path)
+  call rpcnotify(s:job_id, 'did_open', l:lang_id, l:buf_id, l:cur_path)
+endfunction
+
+function! lspc#did_save()
+  call rpcnotify(s:job_id, 'did_save', bufnr())
+endfunction
+
+function! lspc#code_lens()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  call rpcnotify(s:job_id, 'code_lens', l:lang_id, l:cur_path)
+endfunction
+
+" Run the code lens of current line
+function! lspc#code_lens_action()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'code_lens_action', l:lang_id, l:cur_path, l:position)
+endfunction
+
+" Last visual selection as a range, or the cursor position when not `visual`
+function! s:selection(visual) abort
+  if !a:visual
+    let l:position = lspc#buffer#position()
+    return {'start': l:position, 'end': l:position}
+  endif
+
+  let l:start = getpos("'<")[1:2]
+  let l:end = getpos("'>")[1:2]
+  let l:selection = get(b:, 'lspc_selection', {})
+  if get(l:selection, 'visual', []) == [l:start, l:end] && visualmode() ==# 'v'
+    return l:selection['range']
+  endif
+
+  if visualmode() ==# 'V'
+    let l:start[1] = 1
+    let l:end[1] = strlen(getline(l:end[0]))
+  endif
+  return {
+        \ 'start': {'line': l:start[0] - 1, 'character': l:start[1] - 1},
+        \ 'end': {'line': l:end[0] - 1, 'character': min([l:end[1], strlen(getline(l:end[0]))])},
+        \ }
+endfunction
+
+function! lspc#expand_selection(visual)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:range = s:selection(a:visual)
+  call rpcnotify(s:job_id, 'expand_selection', l:lang_id, l:cur_path, l:range)
+endfunction
+
+" Run a runnable at cursor in a terminal, or in background with its
+" errors in quickfix
+function! lspc#runnables(background)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  let l:background = a:background ? v:true : v:false
+  call rpcnotify(s:job_id, 'runnables', l:lang_id, l:cur_path, l:position, l:background)
+endfunction
+
+" Structural search and replace in the current file, the last visual
+" selection or the whole workspace
+function! lspc#ssr(query, scope)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:range = s:selection(a:scope ==# 'selection')
+  call rpcnotify(s:job_id, 'ssr', l:lang_id, l:cur_path, a:query, a:scope, l:range)
+endfunction
+
+let s:rust_analyzer_extensions = [
+      \ 'expand_macro', 'parent_module', 'join_lines',
+      \ 'matching_brace', 'syntax_tree', 'analyzer_status',
+      \ ]
+
+function! lspc#complete_rust_analyzer(arglead, cmdline, cursorpos)
+  return filter(copy(s:rust_analyzer_extensions), 'stridx(v:val, a:arglead) == 0')
+endfunction
+
+" Send a rust-analyzer extension request for the cursor or the last
+" visual selection
+function! lspc#rust_analyzer(extension, visual)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:range = s:selection(a:visual)
+  call rpcnotify(s:job_id, 'rust_analyzer', l:lang_id, l:cur_path, a:extension, l:range)
+endfunction
+
+function! lspc#shrink_selection()
+  let l:cur_path = lspc#buffer#filename()
+  let l:range = s:selection(1)
+  call rpcnotify(s:job_id, 'shrink_selection', l:cur_path, l:range)
+endfunction
+
+" Highlight visible lines, before tokens of the whole buffer are known
+function! lspc#semantic_tokens()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:viewport = lspc#buffer#viewport()
+  let l:range = {
+        \ 'start': {'line': l:viewport.start, 'character': 0},
+        \ 'end': {'line': l:viewport.end, 'character': 0},
+        \ }
+  call rpcnotify(s:job_id, 'semantic_tokens', l:lang_id, l:cur_path, l:range)
+endfunction
+
+function! lspc#incoming_calls()
+  call s:call_hierarchy(v:true)
+endfunction
+
+function! lspc#outgoing_calls()
+  call s:call_hierarchy(v:false)
+endfunction
+
+function! s:call_hierarchy(incoming)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'call_hierarchy', l:lang_id, l:cur_path, l:position, a:incoming)
+endfunction
+
+" Supertypes or subtypes of the type under cursor as a tree, or the
+" direct ones in the location list when called with 1
+function! lspc#supertypes(...)
+  call s:type_hierarchy(v:true, a:0 > 0 && a:1)
+endfunction
+
+function! lspc#subtypes(...)
+  call s:type_hierarchy(v:false, a:0 > 0 && a:1)
+endfunction
+
+function! s:type_hierarchy(supertypes, flat)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  let l:flat = a:flat ? v:true : v:false
+  call rpcnotify(s:job_id, 'type_hierarchy', l:lang_id, l:cur_path, l:position, a:supertypes, l:flat)
+endfunction
+
+" Request children of a node in a tree view
+function! lspc#tree_expand(tree, node_id)
+  call rpcnotify(s:job_id, 'tree_expand', a:tree, a:node_id)
+endfunction
+
+" Let the user choose one of `items` once Neovim is idle, so that the
+" server is not waited for. The index of the chosen item is sent back,
+" or null if nothing is chosen
+function! lspc#select(id, prompt, items)
+  call timer_start(0, {-> s:select(a:id, a:prompt, a:items)})
+endfunction
+
+function! s:select(id, prompt, items)
+  let l:choices = [a:prompt]
+  for l:index in range(len(a:items))
+    call add(l:choices, (l:index + 1) . '. ' . a:items[l:index])
+  endfor
+  let l:choice = inputlist(l:choices)
+  let l:index = l:choice >= 1 && l:choice <= len(a:items) ? l:choice - 1 : v:null
+  call rpcnotify(s:job_id, 'selected', a:id, l:index)
+endfunction
+
+" Indices of the changes of previewed edit `id` to apply, or null if
+" it is cancelled
+function! lspc#previewed(id, accepted)
+  call rpcnotify(s:job_id, 'previewed', a:id, a:accepted)
+endfunction
+
+function! lspc#open_link()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'open_link', l:lang_id, l:cur_path, l:position)
+endfunction
+
+function! lspc#color_presentation()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'color_presentation', l:lang_id, l:cur_path, l:position)
 endfunction
 
 function! lspc#goto_definition()
@@ -81,6 +365,44 @@ function! lspc#inlay_hints()
   call rpcnotify(s:job_id, 'inlay_hints', l:lang_id, l:cur_path)
 endfunction
 
+function! lspc#document_symbol()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  call rpcnotify(s:job_id, 'document_symbol', l:lang_id, l:cur_path)
+endfunction
+
+function! lspc#workspace_symbol(...)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:query = a:0 > 0 ? a:1 : input('Symbol: ')
+  call rpcnotify(s:job_id, 'workspace_symbol', l:lang_id, l:cur_path, l:query)
+endfunction
+
+" Rename the symbol at cursor to `name`, or to the one typed
+function! lspc#rename(...)
+  let l:new_name = a:0 > 0 ? a:1 : input('Rename to: ', expand('<cword>'))
+  if empty(l:new_name)
+    return
+  endif
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'rename', l:lang_id, l:cur_path, l:position, l:new_name)
+endfunction
+
+function! lspc#capabilities()
+  let l:lang_id = 'rust'
+  call rpcnotify(s:job_id, 'capabilities', l:lang_id)
+endfunction
+
+function! lspc#code_action()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:position = lspc#buffer#position()
+  let l:range = {'start': l:position, 'end': l:position}
+  call rpcnotify(s:job_id, 'code_action', l:lang_id, l:cur_path, l:range)
+endfunction
+
 function! lspc#format_doc()
   let l:lang_id = 'rust'
   let l:cur_path = lspc#buffer#filename()
@@ -88,6 +410,45 @@ function! lspc#format_doc()
   call rpcnotify(s:job_id, 'format_doc', l:lang_id, l:cur_path, l:lines)
 endfunction
 
+" Called before writing a buffer, blocks until the server formatting
+" is applied or timed out. Enabled with `format_on_save` in the config
+function! lspc#format_on_save()
+  let l:lang_id = 'rust'
+  " Saving blocks on the server, only wait when formatting is wanted
+  if !lspc#started() || &filetype !=# l:lang_id
+        \ || empty(filter(copy(s:configs(l:lang_id)), 'get(v:val, "format_on_save", 0)'))
+    return
+  endif
+  let l:cur_path = lspc#buffer#filename()
+  try
+    call rpcrequest(s:job_id, 'format_on_save', l:lang_id, l:cur_path)
+  catch
+    echohl WarningMsg | echom '[LSPC] ' . v:exception | echohl None
+  endtry
+endfunction
+
+" Format the visual selection, or v:count lines from v:lnum when used
+" as 'formatexpr'
+function! lspc#format_range()
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:lines = lspc#buffer#text()
+  let l:range = {
+        \ 'start': {'line': lspc#buffer#range_start_line(), 'character': 0},
+        \ 'end': {'line': lspc#buffer#range_end_line(), 'character': 0},
+        \ }
+  call rpcnotify(s:job_id, 'format_range', l:lang_id, l:cur_path, l:lines, l:range)
+  return 0
+endfunction
+
+function! lspc#format_on_type(char)
+  let l:lang_id = 'rust'
+  let l:cur_path = lspc#buffer#filename()
+  let l:lines = lspc#buffer#text()
+  let l:position = lspc#buffer#position()
+  call rpcnotify(s:job_id, 'format_on_type', l:lang_id, l:cur_path, l:lines, l:position, a:char)
+endfunction
+
 function! lspc#hello_from_the_other_side()
   call rpcnotify(s:job_id, 'hello')
 endfunction
@@ -0,0 +1,18 @@
+" Swatches of the colors of a buffer, at the end of their lines.
+" `colors` are [line, '#rrggbb']
+function! lspc#color#update(bufnr, colors) abort
+  let l:ns_id = nvim_create_namespace('lspc_document_color')
+  call nvim_buf_clear_namespace(a:bufnr, l:ns_id, 0, -1)
+
+  let l:chunks_by_line = {}
+  for [l:line, l:hex] in a:colors
+    " Defined again in case the colorscheme has cleared it
+    let l:group = 'LspcColor_' . l:hex[1:]
+    execute 'highlight' l:group 'guifg=' . l:hex
+    let l:chunks = get(l:chunks_by_line, l:line, [])
+    let l:chunks_by_line[l:line] = add(l:chunks, ['■ ', l:group])
+  endfor
+  for [l:line, l:chunks] in items(l:chunks_by_line)
+    call nvim_buf_set_virtual_text(a:bufnr, l:ns_id, str2nr(l:line), l:chunks, {})
+  endfor
+endfunction
@@ -37,10 +37,18 @@ endfunction
 " Open preview window. Window is open in:
 "   - Floating window on Neovim (0.4.0 or later)
 "   - Preview window on Neovim (0.3.0 or earlier) or Vim
-function! lspc#command#open_hover_preview(bufname, lines, filetype) abort
+"
+" Optional dict argument:
+"   - close_events: events closing the floating window
+"   - highlights: list of [group, line, start_col, end_col] in `lines`
+"
+" Return the floating window id, 0 for preview window
+function! lspc#command#open_hover_preview(bufname, lines, filetype, ...) abort
     " Use local variable since parameter is not modifiable
     let lines = a:lines
     let bufnr = bufnr('%')
+    let options = get(a:000, 0, {})
+    let close_events = get(options, 'close_events', 'CursorMoved,CursorMovedI,InsertEnter')
 
     let use_float_win = s:FLOAT_WINDOW_AVAILABLE
     if use_float_win
@@ -112,6 +120,12 @@ function! lspc#command#open_hover_preview(bufname, lines, filetype) abort
     call setline(1, lines)
     setlocal nomodified nomodifiable
 
+    " Floating window has a top margin and a left margin
+    let offset = use_float_win ? 1 : 0
+    for [group, line, start_col, end_col] in get(options, 'highlights', [])
+        call nvim_buf_add_highlight(0, -1, group, line + offset, start_col + offset, end_col + offset)
+    endfor
+
     wincmd p
 
     if use_float_win
@@ -120,8 +134,232 @@ function! lspc#command#open_hover_preview(bufname, lines, filetype) abort
         let call_after_move = printf('lspc#command#close_floatwin_on_cursor_move(%d, %s)', float_win_id, string(pos))
         let call_on_bufenter = printf('lspc#command#close_floatwin_on_buf_enter(%d, %d)', float_win_id, bufnr)
         augroup plugin-lspc-close-hover
-            execute 'autocmd CursorMoved,CursorMovedI,InsertEnter <buffer> call ' . call_after_move
+            execute 'autocmd ' . close_events . ' <buffer> call ' . call_after_move
             execute 'autocmd BufEnter * call ' . call_on_bufenter
         augroup END
+        return float_win_id
+    endif
+    return 0
+endfunction
+
+" Signature help stays open while typing arguments, `active` is the
+" [start, end) column range of the active parameter in the first line
+function! lspc#command#open_signature_help(lines, filetype, active) abort
+    call lspc#command#close_signature_help()
+
+    let highlights = []
+    if a:active isnot v:null
+        let highlights = [['LspcActiveParameter', 0, a:active[0], a:active[1]]]
     endif
+    let s:signature_help_win = lspc#command#open_hover_preview('__LspcSignatureHelp__', a:lines, a:filetype, {
+    \   'close_events': 'InsertLeave',
+    \   'highlights': highlights,
+    \ })
+endfunction
+
+function! lspc#command#close_signature_help() abort
+    let winnr = win_id2win(get(s:, 'signature_help_win', 0))
+    if winnr != 0
+        execute winnr . 'wincmd c'
+    endif
+endfunction
+
+function! lspc#command#signature_help_visible() abort
+    return win_id2win(get(s:, 'signature_help_win', 0)) != 0
+endfunction
+
+" Visually select from `start` to exclusive `end`, both [line, character]
+" from 0. The range is kept in b:lspc_selection to be sent back as is
+" while the selection is unchanged
+function! lspc#command#set_selection(bufnr, start, end) abort
+    if a:bufnr != bufnr('%')
+        return
+    endif
+
+    let [end_line, end_col] = a:end
+    if end_col == 0 && end_line > a:start[0]
+        " Range ends at a line start, select up to the previous line end
+        let end_line -= 1
+        let end_col = strlen(getline(end_line + 1))
+    endif
+    let visual_start = [a:start[0] + 1, a:start[1] + 1]
+    let visual_end = [end_line + 1, max([end_col, 1])]
+
+    execute "normal! \<Esc>"
+    call cursor(visual_start)
+    normal! v
+    call cursor(visual_end)
+
+    let b:lspc_selection = {
+    \   'visual': [visual_start, visual_end],
+    \   'range': {
+    \       'start': {'line': a:start[0], 'character': a:start[1]},
+    \       'end': {'line': a:end[0], 'character': a:end[1]},
+    \   },
+    \ }
+endfunction
+
+" Echo `msg` as is with highlight group `hl`, or none if v:null
+function! lspc#command#echo(msg, hl) abort
+    if a:hl isnot v:null
+        execute 'echohl' a:hl
+    endif
+    echo a:msg
+    echohl None
+endfunction
+
+" Append `lines` to the hidden log buffer `bufname`, open it with
+" `:sbuffer bufname`
+function! lspc#command#append_log(bufname, lines) abort
+    let bufnr = bufnr('^' . a:bufname . '$')
+    if bufnr == -1
+        let bufnr = bufadd(a:bufname)
+        call bufload(bufnr)
+        call setbufvar(bufnr, '&buftype', 'nofile')
+        call setbufvar(bufnr, '&bufhidden', 'hide')
+        call setbufvar(bufnr, '&swapfile', 0)
+        call setbufvar(bufnr, '&buflisted', 0)
+        call setbufline(bufnr, 1, a:lines)
+    else
+        call appendbufline(bufnr, '$', a:lines)
+    endif
+endfunction
+
+" Edit a file link, other links are opened with g:lspc_link_opener
+function! lspc#command#open_link(target, is_file) abort
+    if a:is_file
+        execute 'edit' fnameescape(a:target)
+        return
+    endif
+
+    let opener = get(g:, 'lspc_link_opener', has('mac') ? 'open' : 'xdg-open')
+    call jobstart([opener, a:target], {'detach': v:true})
+endfunction
+
+" Last run, as a function and its arguments
+let s:last_run = v:null
+
+" Run `cmd` in a terminal at the bottom of the screen
+function! lspc#command#run_in_terminal(name, cmd, cwd, env) abort
+    let s:last_run = ['lspc#command#run_in_terminal', [a:name, a:cmd, a:cwd, a:env]]
+    botright new
+    let options = {'env': a:env}
+    if a:cwd isnot v:null
+        let options['cwd'] = a:cwd
+    endif
+    call termopen(a:cmd, options)
+    let b:term_title = a:name
+    wincmd p
+endfunction
+
+" Run `cmd` in background, then fill the quickfix list with compiler
+" messages of cargo, in JSON, and with test panics of its output
+function! lspc#command#run_in_quickfix(name, cmd, cwd, env) abort
+    let s:last_run = ['lspc#command#run_in_quickfix', [a:name, a:cmd, a:cwd, a:env]]
+    let job = {
+          \ 'name': a:name,
+          \ 'cwd': a:cwd,
+          \ 'env': a:env,
+          \ 'stdout_buffered': v:true,
+          \ 'stderr_buffered': v:true,
+          \ 'lines': [],
+          \ }
+    if a:cwd is v:null
+        call remove(job, 'cwd')
+    endif
+
+    function! job.on_stdout(job_id, data, event) abort
+        call extend(self.lines, a:data)
+    endfunction
+    let job.on_stderr = job.on_stdout
+
+    function! job.on_exit(job_id, status, event) abort
+        let cwd = get(self, 'cwd', getcwd())
+        let items = s:quickfix_items(self.lines, cwd)
+        call setqflist([], ' ', {'title': self.name, 'items': items})
+        if a:status == 0
+            echo self.name . ': succeeded'
+        else
+            echohl ErrorMsg | echo self.name . ': failed' | echohl None
+            if !empty(items)
+                copen
+            endif
+        endif
+    endfunction
+
+    call jobstart(a:cmd, job)
+    echo a:name . ': running'
+endfunction
+
+function! s:quickfix_items(lines, cwd) abort
+    let items = []
+    for line in a:lines
+        if line[0] ==# '{'
+            try
+                let message = json_decode(line)
+            catch
+                continue
+            endtry
+            if get(message, 'reason', '') !=# 'compiler-message'
+                continue
+            endif
+            let diagnostic = message.message
+            for span in filter(copy(diagnostic.spans), 'v:val.is_primary')
+                call add(items, {
+                      \ 'filename': s:path(a:cwd, span.file_name),
+                      \ 'lnum': span.line_start,
+                      \ 'col': span.column_start,
+                      \ 'type': toupper(diagnostic.level[0]),
+                      \ 'text': diagnostic.message,
+                      \ })
+            endfor
+        else
+            " Like "thread 'x' panicked at src/lib.rs:3:5:" or, before,
+            " "thread 'x' panicked at 'message', src/lib.rs:3:5"
+            let panic = matchlist(line, 'panicked at \%(''.*'', \)\?\(\f\+\):\(\d\+\):\(\d\+\)')
+            if !empty(panic)
+                call add(items, {
+                      \ 'filename': s:path(a:cwd, panic[1]),
+                      \ 'lnum': str2nr(panic[2]),
+                      \ 'col': str2nr(panic[3]),
+                      \ 'type': 'E',
+                      \ 'text': line,
+                      \ })
+            endif
+        endif
+    endfor
+    return items
+endfunction
+
+function! s:path(cwd, file) abort
+    return a:file[0] ==# '/' ? a:file : a:cwd . '/' . a:file
+endfunction
+
+" Run again what was last run in a terminal or in quickfix
+function! lspc#command#rerun() abort
+    if s:last_run is v:null
+        echo 'Nothing has been run yet'
+        return
+    endif
+    call call(s:last_run[0], s:last_run[1])
+endfunction
+
+" Open a scratch buffer named `bufname` in a split, or reuse its window
+" if it is already visible, and replace its content with `lines`
+function! lspc#command#open_scratch(bufname, lines, filetype) abort
+    let winnr = bufwinnr('^' . a:bufname . '$')
+    if winnr == -1
+        execute 'silent! noswapfile split' a:bufname
+    else
+        execute winnr . 'wincmd w'
+    endif
+
+    setlocal buftype=nofile bufhidden=wipe nobuflisted noswapfile modifiable
+    silent! %delete _
+    call setline(1, a:lines)
+
+    if a:filetype isnot v:null
+        let &l:filetype = a:filetype
+    endif
+    setlocal nomodified nomodifiable
 endfunction
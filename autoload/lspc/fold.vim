@@ -0,0 +1,33 @@
+" Folds computed from the server folding ranges. Enable them with:
+"
+"   setlocal foldmethod=expr foldexpr=lspc#fold#foldexpr()
+
+function! lspc#fold#foldexpr() abort
+  return get(get(b:, 'lspc_fold_levels', []), v:lnum - 1, '0')
+endfunction
+
+" Kinds of folds closed when a buffer is opened
+function! s:closed_kinds() abort
+  return get(g:, 'lspc_fold_closed_kinds', ['imports'])
+endfunction
+
+" `folds` are [start line, kind] of each range
+function! lspc#fold#update(bufnr, levels, folds) abort
+  call setbufvar(a:bufnr, 'lspc_fold_levels', a:levels)
+  if a:bufnr != bufnr('%') || &l:foldexpr !=# 'lspc#fold#foldexpr()'
+    return
+  endif
+
+  " Force folds to be computed again
+  setlocal foldmethod=expr
+
+  if get(b:, 'lspc_folds_closed', 0)
+    return
+  endif
+  let b:lspc_folds_closed = 1
+  for [l:line, l:kind] in a:folds
+    if index(s:closed_kinds(), l:kind) != -1
+      execute 'silent! ' . l:line . 'foldclose'
+    endif
+  endfor
+endfunction
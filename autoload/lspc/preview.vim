@@ -0,0 +1,95 @@
+" Preview of a workspace edit in a diff scratch buffer. Each change, a
+" hunk of a file or a file operation, can be skipped before the edit is
+" applied. The server is told which changes are applied, or that the edit
+" is cancelled when the buffer is closed.
+"
+" `starts` are the first lines of the changes in `lines`, 0-based
+
+let s:preview = {}
+
+function! lspc#preview#open(id, title, lines, starts) abort
+  " Only one edit is previewed at a time
+  call s:answer(v:null)
+
+  let l:help = '# ' . a:title . ': <CR> applies, x skips the hunk under cursor, q cancels'
+  call lspc#command#open_scratch('__LspcPreview__', [l:help] + a:lines, 'diff')
+  " Lines of the buffer, 1-based after the help line
+  let s:preview = {
+        \ 'id': a:id,
+        \ 'starts': map(copy(a:starts), 'v:val + 2'),
+        \ 'skipped': {},
+        \ }
+
+  nnoremap <buffer> <silent> x :call lspc#preview#toggle()<CR>
+  nnoremap <buffer> <silent> <CR> :call lspc#preview#apply()<CR>
+  nnoremap <buffer> <silent> q :call lspc#preview#cancel()<CR>
+  augroup lspc_preview
+    autocmd! * <buffer>
+    autocmd BufWipeout <buffer> call lspc#preview#cancel_wiped()
+  augroup END
+  call s:render()
+endfunction
+
+" Skip the change under cursor, or take it back
+function! lspc#preview#toggle() abort
+  let l:index = s:change_under_cursor()
+  if l:index == -1
+    return
+  endif
+
+  if has_key(s:preview.skipped, l:index)
+    call remove(s:preview.skipped, l:index)
+  else
+    let s:preview.skipped[l:index] = 1
+  endif
+  call s:render()
+endfunction
+
+function! lspc#preview#apply() abort
+  if empty(s:preview)
+    return
+  endif
+
+  let l:accepted = filter(range(len(s:preview.starts)), '!has_key(s:preview.skipped, v:val)')
+  call s:answer(l:accepted)
+  bwipeout
+endfunction
+
+function! lspc#preview#cancel() abort
+  call s:answer(v:null)
+  bwipeout
+endfunction
+
+function! lspc#preview#cancel_wiped() abort
+  call s:answer(v:null)
+endfunction
+
+" Send the indices of the changes to apply, v:null to cancel the edit
+function! s:answer(accepted) abort
+  if empty(s:preview)
+    return
+  endif
+
+  let l:id = s:preview.id
+  let s:preview = {}
+  call lspc#previewed(l:id, a:accepted)
+endfunction
+
+function! s:change_under_cursor() abort
+  let l:index = -1
+  for l:i in range(len(s:preview.starts))
+    if s:preview.starts[l:i] <= line('.')
+      let l:index = l:i
+    endif
+  endfor
+  return l:index
+endfunction
+
+function! s:render() abort
+  let l:ns_id = nvim_create_namespace('lspc_preview')
+  call nvim_buf_clear_namespace(0, l:ns_id, 0, -1)
+  for l:index in keys(s:preview.skipped)
+    let l:line = s:preview.starts[str2nr(l:index)] - 1
+    call nvim_buf_set_virtual_text(0, l:ns_id, l:line, [['skipped', 'LspcSkippedChange']], {})
+  endfor
+endfunction
@@ -0,0 +1,46 @@
+" Work done progress of servers by token, as
+" {server, title, message, percentage}
+let s:progress = {}
+
+function! lspc#progress#update(server, token, kind, title, message, percentage) abort
+  let l:key = a:server . ':' . a:token
+  if a:kind ==# 'end'
+    silent! call remove(s:progress, l:key)
+  else
+    let l:progress = get(s:progress, l:key, {})
+    let s:progress[l:key] = {
+          \ 'server': a:server,
+          \ 'title': a:title,
+          \ 'message': a:message isnot v:null ? a:message : get(l:progress, 'message', v:null),
+          \ 'percentage': a:percentage isnot v:null ? a:percentage : get(l:progress, 'percentage', v:null),
+          \ }
+  endif
+
+  let g:lspc_progress = {
+        \ 'server': a:server,
+        \ 'kind': a:kind,
+        \ 'title': a:title,
+        \ 'message': a:message,
+        \ 'percentage': a:percentage,
+        \ }
+  if exists('#User#LspcProgress')
+    doautocmd <nomodeline> User LspcProgress
+  endif
+  redrawstatus!
+endfunction
+
+" Progress in progress, to be used in 'statusline'
+function! lspc#progress#status() abort
+  let l:parts = []
+  for l:progress in values(s:progress)
+    let l:text = l:progress.server . ': ' . l:progress.title
+    if l:progress.message isnot v:null
+      let l:text .= ' ' . l:progress.message
+    endif
+    if l:progress.percentage isnot v:null
+      let l:text .= ' (' . l:progress.percentage . '%)'
+    endif
+    call add(l:parts, l:text)
+  endfor
+  return join(l:parts, ', ')
+endfunction
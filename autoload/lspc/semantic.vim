@@ -0,0 +1,50 @@
+" Highlight groups of semantic token types. A `type.modifier` key takes
+" precedence over `type`, override them with g:lspc_semantic_highlights
+let s:default_highlights = {
+      \ 'namespace': 'Include',
+      \ 'type': 'Type',
+      \ 'class': 'Type',
+      \ 'enum': 'Type',
+      \ 'interface': 'Type',
+      \ 'struct': 'Type',
+      \ 'typeParameter': 'Type',
+      \ 'parameter': 'Identifier',
+      \ 'variable': 'Identifier',
+      \ 'variable.readonly': 'Constant',
+      \ 'property': 'Identifier',
+      \ 'enumMember': 'Constant',
+      \ 'function': 'Function',
+      \ 'method': 'Function',
+      \ 'macro': 'Macro',
+      \ 'keyword': 'Keyword',
+      \ 'modifier': 'StorageClass',
+      \ 'comment': 'Comment',
+      \ 'string': 'String',
+      \ 'number': 'Number',
+      \ 'regexp': 'String',
+      \ 'operator': 'Operator',
+      \ }
+
+function! s:group(type, modifiers) abort
+  let l:highlights = extend(copy(s:default_highlights), get(g:, 'lspc_semantic_highlights', {}))
+  for l:modifier in a:modifiers
+    let l:group = get(l:highlights, a:type . '.' . l:modifier, '')
+    if !empty(l:group)
+      return l:group
+    endif
+  endfor
+  return get(l:highlights, a:type, '')
+endfunction
+
+" Replace highlights from `start_line` to exclusive `end_line` (-1 for
+" the whole buffer). `tokens` are [line, start, length, type, modifiers]
+function! lspc#semantic#update(bufnr, start_line, end_line, tokens) abort
+  let l:ns_id = nvim_create_namespace('lspc_semantic_tokens')
+  call nvim_buf_clear_namespace(a:bufnr, l:ns_id, a:start_line, a:end_line)
+  for [l:line, l:start, l:length, l:type, l:modifiers] in a:tokens
+    let l:group = s:group(l:type, l:modifiers)
+    if !empty(l:group)
+      call nvim_buf_add_highlight(a:bufnr, l:ns_id, l:group, l:line, l:start, l:start + l:length)
+    endif
+  endfor
+endfunction
@@ -0,0 +1,109 @@
+" Tree views in scratch buffers named after the tree. Children of a node
+" are requested from the server the first time it is expanded.
+"
+" Items are {id, text, location, expandable}, where `location` is
+" {filename, lnum, col} or v:null
+
+function! lspc#tree#open(tree, items) abort
+  call lspc#command#open_scratch(a:tree, [], v:null)
+  let b:lspc_tree = {'nodes': {}, 'roots': [], 'lines': []}
+  for l:item in a:items
+    call s:add_node(l:item)
+    call add(b:lspc_tree.roots, l:item.id)
+  endfor
+
+  nnoremap <buffer> <silent> o :call lspc#tree#toggle()<CR>
+  nnoremap <buffer> <silent> <CR> :call lspc#tree#jump()<CR>
+  call s:render()
+endfunction
+
+function! lspc#tree#add_children(tree, parent, items) abort
+  let l:winnr = bufwinnr('^' . a:tree . '$')
+  if l:winnr == -1
+    return
+  endif
+  let l:cur_winnr = winnr()
+  execute l:winnr . 'wincmd w'
+
+  let l:node = get(get(b:, 'lspc_tree', {'nodes': {}}).nodes, a:parent, {})
+  if !empty(l:node)
+    let l:node.children = []
+    for l:item in a:items
+      call s:add_node(l:item)
+      call add(l:node.children, l:item.id)
+    endfor
+    let l:node.expandable = !empty(a:items)
+    call s:render()
+  endif
+
+  execute l:cur_winnr . 'wincmd w'
+endfunction
+
+" Expand or collapse the node under cursor
+function! lspc#tree#toggle() abort
+  let l:node = s:node_under_cursor()
+  if empty(l:node) || !l:node.expandable
+    return
+  endif
+
+  let l:node.expanded = !l:node.expanded
+  if l:node.expanded && l:node.children is v:null
+    call lspc#tree_expand(bufname('%'), l:node.id)
+  endif
+  call s:render()
+endfunction
+
+" Jump to the location of the node under cursor in the previous window
+function! lspc#tree#jump() abort
+  let l:node = s:node_under_cursor()
+  if empty(l:node) || l:node.location is v:null
+    return
+  endif
+
+  wincmd p
+  execute 'edit' fnameescape(l:node.location.filename)
+  call cursor(l:node.location.lnum, l:node.location.col)
+endfunction
+
+function! s:add_node(item) abort
+  let b:lspc_tree.nodes[a:item.id] = extend(copy(a:item), {'children': v:null, 'expanded': 0})
+endfunction
+
+function! s:node_under_cursor() abort
+  let l:id = get(get(b:, 'lspc_tree', {'lines': []}).lines, line('.') - 1, v:null)
+  return l:id is v:null ? {} : b:lspc_tree.nodes[l:id]
+endfunction
+
+function! s:render_node(id, depth, lines) abort
+  let l:node = b:lspc_tree.nodes[a:id]
+  if !l:node.expandable
+    let l:marker = '  '
+  elseif l:node.expanded
+    let l:marker = '- '
+  else
+    let l:marker = '+ '
+  endif
+  call add(a:lines, repeat('  ', a:depth) . l:marker . l:node.text)
+  call add(b:lspc_tree.lines, a:id)
+
+  if l:node.expanded
+    for l:child in l:node.children is v:null ? [] : l:node.children
+      call s:render_node(l:child, a:depth + 1, a:lines)
+    endfor
+  endif
+endfunction
+
+function! s:render() abort
+  let l:lines = []
+  let b:lspc_tree.lines = []
+  for l:root in b:lspc_tree.roots
+    call s:render_node(l:root, 0, l:lines)
+  endfor
+
+  let l:view = winsaveview()
+  setlocal modifiable
+  silent! %delete _
+  call setline(1, l:lines)
+  setlocal nomodified nomodifiable
+  call winrestview(l:view)
+endfunction
@@ -1,5 +1,32 @@
 " Commands
 command! -nargs=0 LspcStart call lspc#init()
+command! -nargs=+ -complete=customlist,lspc#complete_server_commands
+      \ LspcExecuteCommand call lspc#execute_command(<q-args>)
+command! -nargs=1 -range -bang LspcSsr
+      \ call lspc#ssr(<q-args>, <bang>0 ? 'workspace' : <range> > 0 ? 'selection' : 'file')
+command! -nargs=0 -bang LspcRunnables call lspc#runnables(<bang>0)
+command! -nargs=0 LspcRerun call lspc#command#rerun()
+command! -nargs=1 -range -complete=customlist,lspc#complete_rust_analyzer
+      \ LspcRustAnalyzer call lspc#rust_analyzer(<q-args>, <range> > 0)
+
+" Mappings
+nnoremap <silent> <Plug>(lspc-expand-selection) :<C-u>call lspc#expand_selection(0)<CR>
+xnoremap <silent> <Plug>(lspc-expand-selection) :<C-u>call lspc#expand_selection(1)<CR>
+xnoremap <silent> <Plug>(lspc-shrink-selection) :<C-u>call lspc#shrink_selection()<CR>
+
+highlight default link LspcError ErrorMsg
+highlight default link LspcWarning WarningMsg
+highlight default link LspcInformation Comment
+highlight default link LspcHint Comment
+highlight default link LspcActiveParameter Underlined
+highlight default link LspcHighlightText Visual
+highlight default link LspcHighlightRead Search
+highlight default link LspcHighlightWrite IncSearch
+highlight default link LspcCodeLens Comment
+highlight default link LspcTypeHint Comment
+highlight default link LspcParameterHint Comment
+highlight default link LspcChainingHint Comment
+highlight default link LspcSkippedChange WarningMsg
 
 augroup lspc
   autocmd!
@@ -7,5 +34,14 @@ augroup lspc
     autocmd VimEnter         * call lspc#init()
   endif
   autocmd BufNewFile,BufRead * call lspc#did_open()
+  autocmd TextChangedI       * call lspc#on_text_changed_i()
+  autocmd CursorHold         * call lspc#document_highlight()
+  autocmd CursorMoved        * call lspc#clear_document_highlight()
+  autocmd BufWinEnter        * call lspc#semantic_tokens()
+  if exists('##WinScrolled')
+    autocmd WinScrolled      * call lspc#semantic_tokens()
+  endif
+  autocmd BufWritePre        * call lspc#format_on_save()
+  autocmd BufWritePost       * call lspc#did_save()
   autocmd VimLeave           * call lspc#destroy()
 augroup END
@@ -1,12 +1,17 @@
+pub mod diff;
 pub mod handler;
 // Custom LSP types
 pub mod msg;
+pub mod rust_analyzer;
 pub mod types;
+pub mod watcher;
 
 use std::{
+    cell::RefCell,
     collections::HashMap,
     io,
     path::{Path, PathBuf},
+    rc::Rc,
     time::{Duration, Instant},
 };
 
@@ -14,30 +19,119 @@ use crossbeam::channel::{tick, Receiver, Select};
 use lsp_types::{
     self as lsp,
     notification::{self as noti},
-    request::{Formatting, GotoDefinition, GotoDefinitionResponse, HoverRequest, Initialize},
-    DocumentFormattingParams, FormattingOptions, Hover, Location, Position, ShowMessageParams,
-    TextDocumentIdentifier, TextEdit,
+    request::{
+        CodeLensRequest, CodeLensResolve, ColorPresentationRequest, DocumentColor,
+        DocumentHighlightRequest, DocumentSymbolRequest, ExecuteCommand, FoldingRangeRequest,
+        Formatting, GotoDefinition, GotoDefinitionResponse, HoverRequest, OnTypeFormatting,
+        RangeFormatting, RegisterCapability, SelectionRangeRequest, ShowMessageRequest,
+        SignatureHelpRequest, UnregisterCapability, WorkspaceConfiguration,
+        WorkspaceFoldersRequest, WorkspaceSymbol,
+    },
+    CodeActionContext, CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability,
+    CodeLens, CodeLensParams, ColorInformation, ColorPresentation, ColorPresentationParams,
+    ColorProviderCapability, Diagnostic, DocumentColorParams, DocumentFormattingParams,
+    DocumentHighlight, DocumentLinkParams, DocumentOnTypeFormattingParams,
+    DocumentRangeFormattingParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    ExecuteCommandParams, FoldingRange, FoldingRangeParams, FoldingRangeProviderCapability,
+    FormattingOptions, Hover, Location, LogMessageParams, MessageActionItem, MessageType, Position,
+    Range, RenameParams, SelectionRange, SelectionRangeParams, SelectionRangeProviderCapability,
+    ServerCapabilities, ShowMessageParams, SignatureHelp, SymbolInformation,
+    TextDocumentIdentifier, TextEdit, WorkspaceEdit, WorkspaceSymbolParams,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use url::Url;
 
 use self::{
-    handler::{LangServerHandler, LangSettings},
+    diff::{edit_hunks, PREVIEW_CONTEXT_LINES},
+    handler::{to_workspace_folder, LangServerHandler, LangSettings, ServerKey},
     msg::{LspMessage, RawNotification, RawRequest, RawResponse},
-    types::{InlayHint, InlayHints, InlayHintsParams},
+    rust_analyzer::{
+        run_client_command, run_runnable, Extension, InlayHints, InlayHintsParams, Runnable,
+        SsrScope,
+    },
+    types::{
+        client_capabilities, workspace_changes, AnnotatedWorkspaceEdit,
+        ApplyAnnotatedWorkspaceEdit, ApplyWorkspaceEditResult, CallHierarchyCallsParams,
+        CallHierarchyIncomingCalls, CallHierarchyItem, CallHierarchyOutgoingCalls,
+        CallHierarchyPrepare, DocumentLinkResolve, DocumentLinks, InlayHint, InlayHintParams,
+        InlayHintRefresh, InlayHintRequest, Progress, RawCodeActions, RawInitialize, RawRename,
+        SemanticToken, SemanticTokens, SemanticTokensDeltaParams, SemanticTokensFull,
+        SemanticTokensFullDelta, SemanticTokensFullDeltaResult, SemanticTokensOptions,
+        SemanticTokensParams, SemanticTokensRange, SemanticTokensRangeParams, TypeHierarchyItem,
+        TypeHierarchyParams, TypeHierarchyPrepare, TypeHierarchySubtypes, TypeHierarchySupertypes,
+        WorkDoneProgress, WorkDoneProgressCreate, WorkspaceChange,
+    },
 };
 
 pub const SYNC_DELAY_MS: u64 = 500;
 pub const TIMER_TICK_MS: u64 = 100;
+pub const FORMAT_ON_SAVE_TIMEOUT_MS: u64 = 1000;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct LsConfig {
+    // Defaults to the command name, servers of the same name
+    // are started once per root
+    #[serde(default)]
+    pub name: Option<String>,
     pub command: Vec<String>,
     pub root_markers: Vec<String>,
     #[serde(default)]
     pub indentation: u64,
     #[serde(default)]
     pub indentation_with_space: bool,
+    #[serde(default)]
+    pub format_on_save: bool,
+    // Show workspace edits as a diff to accept before they are applied,
+    // they are always previewed if the server asks for it
+    #[serde(default)]
+    pub preview_edits: bool,
+    // Lowest level of server log messages kept, `info` by default
+    #[serde(default)]
+    pub log_level: Option<String>,
+    // Sent as is in the initialize request
+    #[serde(default)]
+    pub initialization_options: Option<Value>,
+    // Served to `workspace/configuration` requests by section
+    #[serde(default)]
+    pub settings: Option<Value>,
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub enum LogLevel {
+    Error,
+    Warning,
+    Info,
+    Log,
+}
+
+impl LogLevel {
+    fn parse(level: &str) -> Option<Self> {
+        match level {
+            "error" => Some(LogLevel::Error),
+            "warning" => Some(LogLevel::Warning),
+            "info" => Some(LogLevel::Info),
+            "log" => Some(LogLevel::Log),
+            _ => None,
+        }
+    }
+}
+
+impl From<MessageType> for LogLevel {
+    fn from(typ: MessageType) -> Self {
+        match typ {
+            MessageType::Error => LogLevel::Error,
+            MessageType::Warning => LogLevel::Warning,
+            MessageType::Info => LogLevel::Info,
+            MessageType::Log => LogLevel::Log,
+        }
+    }
+}
+
+impl LsConfig {
+    pub fn name(&self) -> &str {
+        self.name.as_ref().unwrap_or(&self.command[0])
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -53,6 +147,16 @@ pub enum Event<B: BufferId> {
         text_document: TextDocumentIdentifier,
         position: Position,
     },
+    SignatureHelp {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+    },
+    DocumentHighlight {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+    },
     GotoDefinition {
         lang_id: String,
         text_document: TextDocumentIdentifier,
@@ -62,12 +166,53 @@ pub enum Event<B: BufferId> {
         lang_id: String,
         text_document: TextDocumentIdentifier,
     },
+    Capabilities {
+        lang_id: String,
+    },
+    DocumentSymbol {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+    },
+    WorkspaceSymbol {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        query: String,
+    },
+    CodeAction {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        range: Range,
+    },
+    DidChangeConfiguration {
+        lang_id: String,
+        config: LsConfig,
+    },
     FormatDoc {
         lang_id: String,
         text_document_lines: Vec<String>,
         text_document: TextDocumentIdentifier,
     },
+    // Editor waits for the reply before writing the buffer
+    FormatOnSave {
+        request_id: u64,
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+    },
+    FormatRange {
+        lang_id: String,
+        text_document_lines: Vec<String>,
+        text_document: TextDocumentIdentifier,
+        range: Range,
+    },
+    FormatOnType {
+        lang_id: String,
+        text_document_lines: Vec<String>,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+        ch: String,
+    },
     DidOpen {
+        lang_id: String,
         buf_id: B,
         text_document: TextDocumentIdentifier,
     },
@@ -75,10 +220,135 @@ pub enum Event<B: BufferId> {
         buf_id: B,
         version: i64,
         content_change: lsp::TextDocumentContentChangeEvent,
+        // Lines replacing the range of `content_change`, its text is
+        // empty for no line as well as for one empty line
+        line_count: u64,
     },
     DidClose {
         buf_id: B,
     },
+    DidSave {
+        buf_id: B,
+    },
+    CodeLens {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+    },
+    // Run a code lens of the line at `position`
+    CodeLensAction {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+    },
+    // Select the smallest range containing current selection `range`
+    ExpandSelection {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        range: Range,
+    },
+    // Go back to the previous range of the last expanded selection
+    ShrinkSelection {
+        text_document: TextDocumentIdentifier,
+        range: Range,
+    },
+    // Show callers of the item at `position`, or its callees
+    CallHierarchy {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+        incoming: bool,
+    },
+    // Show supertypes of the type at `position`, or its subtypes,
+    // as a tree or a `flat` list of the direct ones
+    TypeHierarchy {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+        supertypes: bool,
+        flat: bool,
+    },
+    // Request children of node `node_id` in tree view `tree`
+    TreeExpand {
+        tree: String,
+        node_id: u64,
+    },
+    // Answer of `Editor::select`, None if nothing is chosen
+    Selected {
+        id: u64,
+        index: Option<usize>,
+    },
+    // Answer of `Editor::preview_workspace_edit`, indices of the accepted
+    // changes or None if the edit is cancelled
+    Previewed {
+        id: u64,
+        accepted: Option<Vec<usize>>,
+    },
+    // Open the document link at `position`
+    OpenLink {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+    },
+    // Choose how to write the color at `position`
+    ColorPresentation {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+    },
+    // Send a rust-analyzer `extension` request for the selected `range`
+    RustAnalyzer {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        extension: Extension,
+        range: Range,
+    },
+    // Structural search and replace with `query`, its paths are
+    // resolved at `position`
+    Ssr {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        query: String,
+        position: Position,
+        scope: SsrScope,
+    },
+    // Run a test, bench or binary at `position`, in a terminal or in
+    // `background`
+    Runnables {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+        background: bool,
+    },
+    // Run `command` of the server of `text_document`
+    ExecuteCommand {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        command: String,
+        arguments: Vec<Value>,
+    },
+    // Rename the symbol at `position` to `new_name` in the workspace
+    Rename {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+        new_name: String,
+    },
+    // Highlight the visible `range`, unless tokens of the whole
+    // document are already known
+    SemanticTokens {
+        lang_id: String,
+        text_document: TextDocumentIdentifier,
+        range: Range,
+    },
+}
+
+// Node of a tree view, its children are requested with `Event::TreeExpand`
+#[derive(Debug)]
+pub struct TreeItem {
+    pub id: u64,
+    pub text: String,
+    pub location: Option<Location>,
+    pub expandable: bool,
 }
 
 #[derive(Debug)]
@@ -152,6 +422,8 @@ pub enum LspcError {
     MainLoop(MainLoopError),
     // Requested lang_id server is not started
     NotStarted,
+    // Server does not provide the requested feature
+    NotSupported(&'static str),
 }
 
 pub trait BufferId: Eq + std::fmt::Debug + std::hash::Hash + Copy + 'static {}
@@ -168,18 +440,157 @@ pub trait Editor: 'static {
         text_document: &TextDocumentIdentifier,
         hover: &Hover,
     ) -> Result<(), EditorError>;
+    // Replace inlay hints of `text_document`
     fn inline_hints(
         &mut self,
         text_document: &TextDocumentIdentifier,
         hints: &Vec<InlayHint>,
     ) -> Result<(), EditorError>;
+    // Number of lines of `text_document`, None if it isn't loaded
+    fn line_count(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+    ) -> Result<Option<u64>, EditorError>;
+    fn show_signature_help(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        signature_help: &SignatureHelp,
+    ) -> Result<(), EditorError>;
+    // Characters typed in insert mode which request `feature`
+    fn set_trigger_characters(
+        &mut self,
+        lang_id: &str,
+        feature: &str,
+        chars: &[String],
+    ) -> Result<(), EditorError>;
+    // Replace highlights of the symbol under cursor in `text_document`
+    fn show_document_highlights(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        highlights: &[DocumentHighlight],
+    ) -> Result<(), EditorError>;
     fn show_message(&mut self, show_message_params: &ShowMessageParams) -> Result<(), EditorError>;
+    // Append to the message log of `server`
+    fn log_message(
+        &mut self,
+        server: &str,
+        log_message_params: &LogMessageParams,
+    ) -> Result<(), EditorError>;
+    // Show work done `progress` of `server`, `token` tells apart
+    // concurrent ones and `title` is the one it began with
+    fn show_progress(
+        &mut self,
+        server: &str,
+        token: &str,
+        title: &str,
+        progress: &WorkDoneProgress,
+    ) -> Result<(), EditorError>;
+    fn show_diagnostics(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        diagnostics: &[Diagnostic],
+    ) -> Result<(), EditorError>;
+    // Let user choose one of `items` without waiting for it, the index
+    // of the chosen item is sent with `Event::Selected` and `id`
+    fn select(&mut self, id: u64, prompt: &str, items: &[String]) -> Result<(), EditorError>;
+    fn show_scratch(
+        &mut self,
+        name: &str,
+        lines: &[String],
+        filetype: Option<&str>,
+    ) -> Result<(), EditorError>;
+    // Replace code lenses of `text_document`, unresolved ones are not shown
+    fn show_code_lenses(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        lenses: &[CodeLens],
+    ) -> Result<(), EditorError>;
+    // Open tree view `tree` with `items` as its roots
+    fn show_tree(&mut self, tree: &str, items: &[TreeItem]) -> Result<(), EditorError>;
+    // Show `items` as children of node `parent` in tree view `tree`
+    fn show_tree_children(
+        &mut self,
+        tree: &str,
+        parent: u64,
+        items: &[TreeItem],
+    ) -> Result<(), EditorError>;
+    // Commands a server of `lang_id` can execute, for completion
+    fn set_server_commands(
+        &mut self,
+        lang_id: &str,
+        commands: &[String],
+    ) -> Result<(), EditorError>;
+    fn open_link(&mut self, target: &Url) -> Result<(), EditorError>;
+    // Replace color swatches of `text_document`
+    fn show_document_colors(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        colors: &[ColorInformation],
+    ) -> Result<(), EditorError>;
+    // Visually select `range` of `text_document`
+    fn set_selection(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        range: &Range,
+    ) -> Result<(), EditorError>;
+    // Replace semantic highlights of `text_document` in lines of `range`,
+    // or in the whole document
+    fn show_semantic_tokens(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        range: Option<&Range>,
+        tokens: &[SemanticToken],
+    ) -> Result<(), EditorError>;
+    // Replace folds of `text_document`
+    fn show_folding_ranges(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        ranges: &[FoldingRange],
+    ) -> Result<(), EditorError>;
+    fn run_in_terminal(
+        &mut self,
+        name: &str,
+        command: &[String],
+        cwd: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<(), EditorError>;
+    // Like `run_in_terminal`, in background with the errors in output
+    // sent to the quickfix list
+    fn run_in_quickfix(
+        &mut self,
+        name: &str,
+        command: &[String],
+        cwd: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<(), EditorError>;
+    // Show `symbols` as (text, location) to jump to
+    fn show_symbols(
+        &mut self,
+        title: &str,
+        symbols: &[(String, Location)],
+    ) -> Result<(), EditorError>;
     fn goto(&mut self, location: &Location) -> Result<(), EditorError>;
     fn apply_edits(&self, lines: &Vec<String>, edits: &Vec<TextEdit>) -> Result<(), EditorError>;
+    fn apply_workspace_edit(&mut self, edit: &WorkspaceEdit) -> Result<(), EditorError> {
+        self.apply_workspace_changes(&workspace_changes(edit))
+    }
+    fn apply_workspace_changes(&mut self, changes: &[WorkspaceChange]) -> Result<(), EditorError>;
+    // Show what each of `changes` of `edit` does without waiting for the
+    // user, the indices of the ones to apply are sent with
+    // `Event::Previewed` and `id`
+    fn preview_workspace_edit(
+        &mut self,
+        id: u64,
+        title: &str,
+        edit: &AnnotatedWorkspaceEdit,
+        changes: &[WorkspaceChange],
+    ) -> Result<(), EditorError>;
     fn watch_file_events(
         &mut self,
         text_document: &TextDocumentIdentifier,
     ) -> Result<(), EditorError>;
+    // Answer a blocking editor request, with `error` if it failed
+    fn reply(&mut self, request_id: u64, error: Option<&str>) -> Result<(), EditorError>;
 }
 
 struct DelayedSync {
@@ -225,6 +636,8 @@ struct TrackingBuffer {
     text_document: TextDocumentIdentifier,
     sent_did_open: bool,
     delayed_sync: DelayedSync,
+    // Lines of the document with every change received, synced or not
+    line_count: u64,
 }
 
 impl TrackingBuffer {
@@ -234,12 +647,26 @@ impl TrackingBuffer {
             delayed_sync: DelayedSync::new(text_document.uri.clone()),
             text_document,
             sent_did_open: false,
+            line_count: 0,
         }
     }
 
+    // `line_count` lines replace the lines of `range`, the whole
+    // document if there is none
+    fn update_line_count(&mut self, range: Option<Range>, line_count: u64) {
+        let removed = range.map_or(self.line_count, |range| {
+            range
+                .end
+                .line
+                .min(self.line_count)
+                .saturating_sub(range.start.line)
+        });
+        self.line_count = self.line_count - removed + line_count;
+    }
+
     fn sync_pending_changes<E: Editor>(
         &mut self,
-        lsp_handler: &mut LangServerHandler<E>,
+        lsp_handlers: &mut [&mut LangServerHandler<E>],
     ) -> Result<(), LspcError> {
         let mut sync_content = lsp::DidChangeTextDocumentParams {
             text_document: lsp::VersionedTextDocumentIdentifier {
@@ -251,7 +678,9 @@ impl TrackingBuffer {
         std::mem::swap(&mut self.delayed_sync.sync_content, &mut sync_content);
 
         if !sync_content.content_changes.is_empty() {
-            lsp_handler.lsp_notify::<noti::DidChangeTextDocument>(sync_content)?;
+            for lsp_handler in lsp_handlers.iter_mut() {
+                lsp_handler.lsp_notify::<noti::DidChangeTextDocument>(sync_content.clone())?;
+            }
             self.delayed_sync.scheduled_at = None;
         }
 
@@ -273,10 +702,188 @@ impl TrackingBuffer {
     }
 }
 
+// Commands to be executed by the server that provided them,
+// queued from response callbacks
+type PendingCommands = Rc<RefCell<Vec<(ServerKey, lsp::Command)>>>;
+
+// Editor request waiting for the response of server request `lsp_request_id`
+struct PendingReply {
+    request_id: u64,
+    server: ServerKey,
+    lsp_request_id: u64,
+    deadline: Instant,
+}
+
+// Removed by the response callback once it replies, the rest are
+// replied with an error when the server fails or is too slow
+type PendingReplies = Rc<RefCell<Vec<PendingReply>>>;
+
+// What tree view nodes stand for, to request their children
+#[derive(Clone)]
+enum TreeNode {
+    Calls {
+        server: ServerKey,
+        item: CallHierarchyItem,
+        incoming: bool,
+    },
+    Types {
+        server: ServerKey,
+        item: TypeHierarchyItem,
+        supertypes: bool,
+    },
+}
+
+// Nodes of each tree view by id, ids are unique across trees
+#[derive(Default)]
+struct TreeNodes {
+    last_id: u64,
+    nodes: HashMap<String, HashMap<u64, TreeNode>>,
+}
+
+impl TreeNodes {
+    fn get(&self, tree: &str, node_id: u64) -> Option<&TreeNode> {
+        self.nodes.get(tree)?.get(&node_id)
+    }
+
+    // Forget the nodes of `tree` when another hierarchy replaces them
+    fn clear(&mut self, tree: &str) {
+        self.nodes.remove(tree);
+    }
+}
+
+// Nodes are added by response callbacks
+type Trees = Rc<RefCell<TreeNodes>>;
+
+// Selection range chain of a document from the innermost range,
+// and the index of the selected one
+struct SelectionChain {
+    ranges: Vec<Range>,
+    selected: usize,
+}
+
+// Updated by selection range callbacks, dropped when the document changes
+type SelectionChains = Rc<RefCell<HashMap<Url, SelectionChain>>>;
+
+// What the user is choosing from, until the editor answers with
+// `Event::Selected`
+enum Choice {
+    CodeAction(Vec<CodeActionItem>),
+    CodeLens {
+        server: ServerKey,
+        commands: Vec<lsp::Command>,
+    },
+    ColorPresentation {
+        uri: Url,
+        range: Range,
+        presentations: Vec<ColorPresentation>,
+    },
+    // Server request `request_id` is answered with the chosen action
+    MessageAction {
+        server: ServerKey,
+        request_id: u64,
+        actions: Vec<MessageActionItem>,
+    },
+    Runnable {
+        runnables: Vec<Runnable>,
+        background: bool,
+    },
+    // Changes of a previewed edit with the index of the change of the
+    // edit they are part of, answered with `Event::Previewed`
+    WorkspaceEdit {
+        changes: Vec<(usize, WorkspaceChange)>,
+        then: AfterEdit,
+    },
+}
+
+// What follows an edit, once it is applied or not
+enum AfterEdit {
+    Nothing,
+    // Command of a code action, run if its edit is applied
+    Command(ServerKey, lsp::Command),
+    // `workspace/applyEdit` request `request_id` is answered
+    Reply { server: ServerKey, request_id: u64 },
+}
+
+#[derive(Default)]
+struct Choices {
+    last_id: u64,
+    pending: HashMap<u64, Choice>,
+}
+
+impl Choices {
+    fn add(&mut self, choice: Choice) -> u64 {
+        self.last_id += 1;
+        self.pending.insert(self.last_id, choice);
+        self.last_id
+    }
+}
+
+// Added by response callbacks, the editor is not waited for
+type PendingChoices = Rc<RefCell<Choices>>;
+
+// Let the user choose one of `items` for `choice`
+fn ask_choice<E: Editor>(
+    editor: &mut E,
+    choices: &PendingChoices,
+    prompt: &str,
+    items: &[String],
+    choice: Choice,
+) -> Result<(), EditorError> {
+    let id = choices.borrow_mut().add(choice);
+    let result = editor.select(id, prompt, items);
+    if result.is_err() {
+        choices.borrow_mut().pending.remove(&id);
+    }
+
+    result
+}
+
 pub struct Lspc<E: Editor> {
     editor: E,
     lsp_handlers: Vec<LangServerHandler<E>>,
     tracking_buffers: HashMap<E::BufferId, TrackingBuffer>,
+    // Latest diagnostics of each document, by server
+    diagnostics: HashMap<Url, HashMap<ServerKey, Vec<Diagnostic>>>,
+    pending_commands: PendingCommands,
+    pending_replies: PendingReplies,
+    // In flight document highlight request, cancelled by the next one
+    document_highlight: Option<(ServerKey, u64)>,
+    selection_chains: SelectionChains,
+    trees: Trees,
+    choices: PendingChoices,
+}
+
+struct CodeActionMerge {
+    remaining: usize,
+    actions: Vec<CodeActionItem>,
+}
+
+impl CodeActionMerge {
+    // Add the actions of one server, all of them once every server
+    // answered. Failed servers are only counted
+    fn add(
+        &mut self,
+        actions: Result<Vec<CodeActionItem>, LangServerError>,
+    ) -> Option<Vec<CodeActionItem>> {
+        self.remaining -= 1;
+        match actions {
+            Ok(actions) => self.actions.extend(actions),
+            Err(e) => log::warn!("Code action request failed: {:?}", e),
+        }
+        if self.remaining > 0 {
+            return None;
+        }
+
+        Some(std::mem::replace(&mut self.actions, Vec::new()))
+    }
+}
+
+// Code action of `server`, with the change annotations of its edit
+struct CodeActionItem {
+    server: ServerKey,
+    action: CodeActionOrCommand,
+    edit: Option<AnnotatedWorkspaceEdit>,
+    preview: bool,
 }
 
 #[derive(Debug)]
@@ -337,257 +944,2332 @@ fn to_file_url(s: &str) -> Option<Url> {
     Url::from_file_path(s).ok()
 }
 
-// Get the handler of a file by checking
-// if that handler's root is ancestor of `file_path`
-fn handler_of<'a, E>(
-    handlers: &'a mut Vec<LangServerHandler<E>>,
-    file_path: &str,
-) -> Option<&'a mut LangServerHandler<E>>
-where
-    E: Editor,
-{
-    handlers
-        .iter_mut()
-        .find(|handler| handler.include_file(file_path))
-}
+// Get the handlers of a file: for each server of `lang_id`, the one
+// whose root is the most specific ancestor of the file at `uri`.
+// Indices are in starting order, so the first one is the primary server.
+fn handler_indices_of<E: Editor>(
+    handlers: &[LangServerHandler<E>],
+    lang_id: &str,
+    uri: &Url,
+) -> Vec<usize> {
+    // Roots are compared with decoded paths, not percent-encoded ones
+    let file_path = match uri.to_file_path() {
+        Ok(file_path) => file_path,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut indices: Vec<usize> = Vec::new();
+    for (index, handler) in handlers.iter().enumerate() {
+        if handler.lang_id != lang_id || !handler.include_file(&file_path) {
+            continue;
+        }
 
-impl<E: Editor> Lspc<E> {
-    fn handler_for(&mut self, lang_id: &str) -> Option<&mut LangServerHandler<E>> {
-        self.lsp_handlers
+        let root_len = |handler: &LangServerHandler<E>| {
+            handler
+                .root_of(&file_path)
+                .map(|root| root.components().count())
+        };
+        let same_server = indices
             .iter_mut()
-            .find(|handler| handler.lang_id == lang_id)
+            .find(|i| handlers[**i].name == handler.name);
+        match same_server {
+            Some(i) => {
+                if root_len(handler) > root_len(&handlers[*i]) {
+                    *i = index;
+                }
+            }
+            None => indices.push(index),
+        }
     }
+    indices.sort();
 
-    fn handler_for_buffer(
-        &mut self,
-        buf_id: &E::BufferId,
-    ) -> Option<(&mut LangServerHandler<E>, &mut TrackingBuffer)> {
-        let tracking_buffer = self.tracking_buffers.get_mut(buf_id)?;
-        let handler = self
-            .lsp_handlers
-            .iter_mut()
-            .find(|handler| handler.lang_id == tracking_buffer.lang_id)?;
-        Some((handler, tracking_buffer))
-    }
+    indices
+}
 
-    fn handle_editor_event(&mut self, event: Event<E::BufferId>) -> Result<(), LspcError> {
-        match event {
-            Event::Hello => {
-                self.editor.say_hello().map_err(|e| LspcError::Editor(e))?;
+// Code actions of `handler` which are valid, with their raw edits
+fn code_action_items<E: Editor>(
+    handler: &LangServerHandler<E>,
+    values: Vec<Value>,
+) -> Vec<CodeActionItem> {
+    let mut actions = Vec::new();
+    for value in values {
+        let action = match serde_json::from_value(value.clone()) {
+            Ok(action) => action,
+            Err(e) => {
+                log::warn!("Invalid code action {}: {}", value, e);
+                continue;
             }
-            Event::StartServer {
-                lang_id,
-                config,
-                cur_path,
-            } => {
-                let capabilities = self.editor.capabilities();
-                let lang_settings = LangSettings {
-                    indentation: config.indentation,
-                    indentation_with_space: config.indentation_with_space,
-                };
+        };
+        let edit = value
+            .get("edit")
+            .and_then(|edit| AnnotatedWorkspaceEdit::from_value(edit.clone()).ok());
+        actions.push(CodeActionItem {
+            server: handler.key(),
+            action,
+            edit,
+            preview: handler.lang_settings.preview_edits,
+        });
+    }
 
-                let cur_path = PathBuf::from(cur_path);
-                let root = find_root_path(&cur_path, &config.root_markers)
-                    .map(|path| path.to_str())
-                    .ok_or_else(|| LspcError::Editor(EditorError::RootPathNotFound))?
-                    .ok_or_else(|| LspcError::Editor(EditorError::RootPathNotFound))?;
+    actions
+}
 
-                let root_url =
-                    to_file_url(&root).ok_or(LspcError::Editor(EditorError::RootPathNotFound))?;
+fn supports_code_action(capabilities: &ServerCapabilities) -> bool {
+    match capabilities.code_action_provider {
+        Some(CodeActionProviderCapability::Simple(supported)) => supported,
+        Some(CodeActionProviderCapability::Options(_)) => true,
+        None => false,
+    }
+}
 
-                let mut lsp_handler = LangServerHandler::new(
-                    lang_id,
-                    &config.command[0],
-                    lang_settings,
-                    &config.command[1..],
-                    root.to_owned(),
+// Show resolved lenses now, and the others when they are resolved
+fn show_code_lenses<E: Editor>(
+    editor: &mut E,
+    handler: &mut LangServerHandler<E>,
+    text_document: TextDocumentIdentifier,
+    lenses: Vec<CodeLens>,
+) -> Result<(), LspcError> {
+    editor.show_code_lenses(&text_document, &lenses)?;
+    let unresolved = lenses
+        .iter()
+        .enumerate()
+        .filter(|(_, lens)| lens.command.is_none())
+        .map(|(index, lens)| (index, lens.clone()))
+        .collect::<Vec<_>>();
+    handler
+        .code_lenses
+        .insert(text_document.uri.clone(), lenses);
+    if !handler.code_lens_resolve_provider() {
+        return Ok(());
+    }
+
+    for (index, lens) in unresolved {
+        let text_document = text_document.clone();
+        handler.lsp_request::<CodeLensResolve>(
+            lens,
+            Box::new(move |editor: &mut E, handler, resolved| {
+                let lenses = match handler.code_lenses.get_mut(&text_document.uri) {
+                    Some(lenses) => lenses,
+                    None => return Ok(()),
+                };
+                // Lenses may have been refreshed since
+                match lenses.get_mut(index) {
+                    Some(lens) if lens.range == resolved.range => *lens = resolved,
+                    _ => return Ok(()),
+                }
+                editor.show_code_lenses(&text_document, lenses)?;
+
+                Ok(())
+            }),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn supports_folding_range(capabilities: &ServerCapabilities) -> bool {
+    match capabilities.folding_range_provider {
+        Some(FoldingRangeProviderCapability::Simple(false)) | None => false,
+        Some(_) => true,
+    }
+}
+
+fn supports_selection_range(capabilities: &ServerCapabilities) -> bool {
+    match capabilities.selection_range_provider {
+        Some(SelectionRangeProviderCapability::Simple(false)) | None => false,
+        Some(_) => true,
+    }
+}
+
+fn supports_document_color(capabilities: &ServerCapabilities) -> bool {
+    match capabilities.color_provider {
+        Some(ColorProviderCapability::Simple(false)) | None => false,
+        Some(_) => true,
+    }
+}
+
+fn range_contains(outer: &Range, inner: &Range) -> bool {
+    let start = |range: &Range| (range.start.line, range.start.character);
+    let end = |range: &Range| (range.end.line, range.end.character);
+    start(outer) <= start(inner) && end(inner) <= end(outer)
+}
+
+// Ranges of `selection_range` and its parents, from the innermost one
+fn selection_ranges(selection_range: SelectionRange) -> Vec<Range> {
+    let mut ranges = vec![selection_range.range];
+    let mut parent = selection_range.parent;
+    while let Some(selection_range) = parent {
+        ranges.push(selection_range.range);
+        parent = selection_range.parent;
+    }
+
+    ranges
+}
+
+// Add an expandable node of the tree view `tree`
+fn tree_item(
+    trees: &Trees,
+    tree: &str,
+    text: String,
+    location: Location,
+    node: TreeNode,
+) -> TreeItem {
+    let mut trees = trees.borrow_mut();
+    trees.last_id += 1;
+    let id = trees.last_id;
+    trees
+        .nodes
+        .entry(tree.to_owned())
+        .or_default()
+        .insert(id, node);
+
+    TreeItem {
+        id,
+        text,
+        location: Some(location),
+        expandable: true,
+    }
+}
+
+// Text of call and type hierarchy items
+fn hierarchy_item_text(item: &CallHierarchyItem) -> String {
+    match item.detail {
+        Some(ref detail) => format!("{} [{:?}] {}", item.name, item.kind, detail),
+        None => format!("{} [{:?}]", item.name, item.kind),
+    }
+}
+
+type TypeHierarchyCallback<E> =
+    Box<dyn FnOnce(&mut E, ServerKey, Vec<TypeHierarchyItem>) -> Result<(), LspcError>>;
+
+// Request supertypes of `item`, or its subtypes
+fn request_type_hierarchy<E: Editor>(
+    handler: &mut LangServerHandler<E>,
+    item: TypeHierarchyItem,
+    supertypes: bool,
+    then: TypeHierarchyCallback<E>,
+) -> Result<(), LspcError> {
+    let params = TypeHierarchyParams { item };
+    if supertypes {
+        handler.lsp_request::<TypeHierarchySupertypes>(
+            params,
+            Box::new(move |editor: &mut E, handler, response| {
+                then(editor, handler.key(), response.unwrap_or_default())
+            }),
+        )?;
+    } else {
+        handler.lsp_request::<TypeHierarchySubtypes>(
+            params,
+            Box::new(move |editor: &mut E, handler, response| {
+                then(editor, handler.key(), response.unwrap_or_default())
+            }),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn type_hierarchy_tree_items(
+    trees: &Trees,
+    tree: &str,
+    server: ServerKey,
+    items: Vec<TypeHierarchyItem>,
+    supertypes: bool,
+) -> Vec<TreeItem> {
+    items
+        .into_iter()
+        .map(|item| {
+            let text = hierarchy_item_text(&item);
+            let location = Location::new(item.uri.clone(), item.selection_range);
+            let node = TreeNode::Types {
+                server: server.clone(),
+                item,
+                supertypes,
+            };
+            tree_item(trees, tree, text, location, node)
+        })
+        .collect()
+}
+
+// Show and keep `tokens` of the whole document
+fn show_semantic_tokens<E: Editor>(
+    editor: &mut E,
+    handler: &mut LangServerHandler<E>,
+    text_document: TextDocumentIdentifier,
+    tokens: SemanticTokens,
+) -> Result<(), LspcError> {
+    if let Some(options) = handler.semantic_tokens_options() {
+        editor.show_semantic_tokens(&text_document, None, &options.legend.decode(&tokens.data))?;
+    }
+    handler.semantic_tokens.insert(text_document.uri, tokens);
+
+    Ok(())
+}
+
+// Merge `extra` into `capabilities`, recursively for objects
+fn extend_capabilities(capabilities: &mut Value, extra: Value) {
+    let (capabilities, extra) = match (capabilities, extra) {
+        (Value::Object(capabilities), Value::Object(extra)) => (capabilities, extra),
+        _ => return,
+    };
+    for (key, value) in extra {
+        match capabilities.get_mut(&key) {
+            Some(existing) if existing.is_object() => extend_capabilities(existing, value),
+            _ => {
+                capabilities.insert(key, value);
+            }
+        }
+    }
+}
+
+fn progress_token(token: &lsp::NumberOrString) -> String {
+    match token {
+        lsp::NumberOrString::Number(number) => number.to_string(),
+        lsp::NumberOrString::String(string) => string.clone(),
+    }
+}
+
+// Optional features are skipped if the server does not provide them
+fn ignore_unsupported(result: Result<(), LspcError>) -> Result<(), LspcError> {
+    match result {
+        Err(LspcError::NotSupported(_)) => Ok(()),
+        result => result,
+    }
+}
+
+fn formatting_options(lang_settings: &LangSettings) -> FormattingOptions {
+    FormattingOptions {
+        tab_size: lang_settings.indentation,
+        insert_spaces: lang_settings.indentation_with_space,
+        properties: HashMap::new(),
+    }
+}
+
+fn symbol_information_item(symbol: &SymbolInformation) -> (String, Location) {
+    let text = match symbol.container_name {
+        Some(ref container_name) => {
+            format!("{} [{:?}] in {}", symbol.name, symbol.kind, container_name)
+        }
+        None => format!("{} [{:?}]", symbol.name, symbol.kind),
+    };
+
+    (text, symbol.location.clone())
+}
+
+// Flatten nested symbols, children are indented under their parent
+fn document_symbol_items(
+    uri: &Url,
+    symbols: &[DocumentSymbol],
+    depth: usize,
+    items: &mut Vec<(String, Location)>,
+) {
+    for symbol in symbols {
+        let text = format!("{}{} [{:?}]", "  ".repeat(depth), symbol.name, symbol.kind);
+        items.push((text, Location::new(uri.clone(), symbol.selection_range)));
+        if let Some(ref children) = symbol.children {
+            document_symbol_items(uri, children, depth + 1, items);
+        }
+    }
+}
+
+// Apply `edit` now, or the changes user accepts in its preview if
+// `preview` is set or its changes need confirmation. `then` is given
+// back if it is to be done now, it is done once the preview is answered
+// otherwise
+fn confirm_workspace_edit<E: Editor>(
+    editor: &mut E,
+    choices: &PendingChoices,
+    title: &str,
+    edit: &AnnotatedWorkspaceEdit,
+    preview: bool,
+    then: AfterEdit,
+) -> Result<Option<AfterEdit>, EditorError> {
+    if !preview && !edit.needs_confirmation() {
+        editor.apply_workspace_edit(&edit.edit)?;
+        return Ok(Some(then));
+    }
+
+    let changes = preview_changes(&edit.edit);
+    let parts = changes
+        .iter()
+        .map(|(_, change)| change.clone())
+        .collect::<Vec<_>>();
+    let id = choices
+        .borrow_mut()
+        .add(Choice::WorkspaceEdit { changes, then });
+    if let Err(e) = editor.preview_workspace_edit(id, title, edit, &parts) {
+        choices.borrow_mut().pending.remove(&id);
+        return Err(e);
+    }
+
+    Ok(None)
+}
+
+// Changes of `edit` with the edits of a document split by hunk, so that
+// they can be accepted one by one, and the index of the change they
+// are part of
+fn preview_changes(edit: &WorkspaceEdit) -> Vec<(usize, WorkspaceChange)> {
+    let mut changes = Vec::new();
+    for (index, change) in workspace_changes(edit).into_iter().enumerate() {
+        match change {
+            WorkspaceChange::Edits(uri, edits) => {
+                for hunk in edit_hunks(&edits, PREVIEW_CONTEXT_LINES) {
+                    changes.push((index, WorkspaceChange::Edits(uri.clone(), hunk)));
+                }
+            }
+            change => changes.push((index, change)),
+        }
+    }
+
+    changes
+}
+
+// Changes of a previewed edit at `accepted`, the accepted hunks of a
+// change go back together as their ranges are of the same text
+fn accepted_changes(
+    changes: Vec<(usize, WorkspaceChange)>,
+    accepted: &[usize],
+) -> Vec<WorkspaceChange> {
+    let mut accepted_changes: Vec<(usize, WorkspaceChange)> = Vec::new();
+    let changes = changes
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| accepted.contains(index))
+        .map(|(_, change)| change);
+    for (index, change) in changes {
+        match (accepted_changes.last_mut(), change) {
+            (
+                Some((last_index, WorkspaceChange::Edits(_, last_edits))),
+                WorkspaceChange::Edits(_, edits),
+            ) if *last_index == index => {
+                last_edits.extend(edits);
+            }
+            (_, change) => accepted_changes.push((index, change)),
+        }
+    }
+
+    accepted_changes
+        .into_iter()
+        .map(|(_, change)| change)
+        .collect()
+}
+
+fn select_code_action<E: Editor>(
+    editor: &mut E,
+    choices: &PendingChoices,
+    actions: Vec<CodeActionItem>,
+) -> Result<(), LspcError> {
+    if actions.is_empty() {
+        editor.message("No code action available")?;
+        return Ok(());
+    }
+
+    let titles = actions
+        .iter()
+        .map(|item| {
+            let title = match item.action {
+                CodeActionOrCommand::Command(ref command) => &command.title,
+                CodeActionOrCommand::CodeAction(ref action) => &action.title,
+            };
+            format!("{} [{}]", title, item.server.name)
+        })
+        .collect::<Vec<_>>();
+    ask_choice(
+        editor,
+        choices,
+        "Code actions:",
+        &titles,
+        Choice::CodeAction(actions),
+    )?;
+
+    Ok(())
+}
+
+impl<E: Editor> Lspc<E> {
+    // Get the first handler of `text_document` which provides `feature`
+    fn handler_for<F>(
+        &mut self,
+        lang_id: &str,
+        text_document: &TextDocumentIdentifier,
+        feature: &'static str,
+        provider: F,
+    ) -> Result<&mut LangServerHandler<E>, LspcError>
+    where
+        F: Fn(&ServerCapabilities) -> bool,
+    {
+        self.find_handler(lang_id, text_document, |handler| {
+            handler.ensure_supports(feature, &provider)
+        })
+    }
+
+    // Like `handler_for`, with the raw capability name of `feature`
+    fn handler_providing(
+        &mut self,
+        lang_id: &str,
+        text_document: &TextDocumentIdentifier,
+        feature: &'static str,
+        provider: &str,
+    ) -> Result<&mut LangServerHandler<E>, LspcError> {
+        self.find_handler(lang_id, text_document, |handler| {
+            handler.ensure_provides(feature, provider)
+        })
+    }
+
+    // Like `handler_for`, for servers whose semantic tokens options
+    // (with their legend) are `supported`
+    fn semantic_tokens_handler<F>(
+        &mut self,
+        lang_id: &str,
+        text_document: &TextDocumentIdentifier,
+        supported: F,
+    ) -> Result<&mut LangServerHandler<E>, LspcError>
+    where
+        F: Fn(&SemanticTokensOptions) -> bool,
+    {
+        self.find_handler(lang_id, text_document, |handler| {
+            handler.ensure_provides("semantic tokens", "semanticTokensProvider")?;
+            match handler.semantic_tokens_options() {
+                Some(options) if supported(options) => Ok(()),
+                _ => Err(LspcError::NotSupported("semantic tokens")),
+            }
+        })
+    }
+
+    fn find_handler<F>(
+        &mut self,
+        lang_id: &str,
+        text_document: &TextDocumentIdentifier,
+        ensure: F,
+    ) -> Result<&mut LangServerHandler<E>, LspcError>
+    where
+        F: Fn(&LangServerHandler<E>) -> Result<(), LspcError>,
+    {
+        let indices = handler_indices_of(&self.lsp_handlers, lang_id, &text_document.uri);
+        let mut found = Err(LspcError::NotStarted);
+        for index in indices {
+            found = ensure(&self.lsp_handlers[index]).map(|_| index);
+            if found.is_ok() {
+                break;
+            }
+        }
+
+        Ok(&mut self.lsp_handlers[found?])
+    }
+
+    fn handlers_for_buffer(
+        &mut self,
+        buf_id: &E::BufferId,
+    ) -> Option<(Vec<&mut LangServerHandler<E>>, &mut TrackingBuffer)> {
+        let tracking_buffer = self.tracking_buffers.get_mut(buf_id)?;
+        let indices = handler_indices_of(
+            &self.lsp_handlers,
+            &tracking_buffer.lang_id,
+            &tracking_buffer.text_document.uri,
+        );
+        let handlers = self
+            .lsp_handlers
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| indices.contains(index))
+            .map(|(_, handler)| handler)
+            .collect::<Vec<_>>();
+        if handlers.is_empty() {
+            return None;
+        }
+
+        Some((handlers, tracking_buffer))
+    }
+
+    // Add the root of a file opened out of the roots of running servers
+    // as a workspace folder, to the first server of each name supporting it
+    fn add_workspace_folder_of(&mut self, lang_id: &str, uri: &Url) -> Result<(), LspcError> {
+        let file_path = match uri.to_file_path() {
+            Ok(file_path) => file_path,
+            Err(_) => return Ok(()),
+        };
+        let mut names = Vec::new();
+        for handler in self.lsp_handlers.iter_mut() {
+            if handler.lang_id != lang_id
+                || names.contains(&handler.name)
+                || !handler.supports_workspace_folders()
+            {
+                continue;
+            }
+            let root = match find_root_path(&file_path, &handler.lang_settings.root_markers)
+                .and_then(|root| root.to_str())
+            {
+                Some(root) => root,
+                None => continue,
+            };
+            let folder = match to_workspace_folder(root) {
+                Some(folder) => folder,
+                None => continue,
+            };
+            handler.add_workspace_folder(folder)?;
+            names.push(handler.name.clone());
+            self.editor.message(&format!(
+                "Added workspace folder {} to {}",
+                root, handler.name
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    // Send pending changes of `text_document`, for requests which
+    // need the content being typed
+    fn sync_document(&mut self, text_document: &TextDocumentIdentifier) -> Result<(), LspcError> {
+        let buf_id = self
+            .tracking_buffers
+            .iter()
+            .find(|(_, tracking_buf)| tracking_buf.text_document.uri == text_document.uri)
+            .map(|(buf_id, _)| *buf_id);
+        if let Some((mut handlers, tracking_buf)) =
+            buf_id.and_then(|buf_id| self.handlers_for_buffer(&buf_id))
+        {
+            tracking_buf.sync_pending_changes(&mut handlers)?;
+        }
+
+        Ok(())
+    }
+
+    // Update what is computed from the document content, after it is
+    // opened or synced
+    fn refresh_document(
+        &mut self,
+        lang_id: &str,
+        text_document: &TextDocumentIdentifier,
+    ) -> Result<(), LspcError> {
+        ignore_unsupported(self.request_folding_ranges(lang_id, text_document.clone()))?;
+        ignore_unsupported(self.request_semantic_tokens(lang_id, text_document.clone()))?;
+        ignore_unsupported(self.request_document_colors(lang_id, text_document.clone()))?;
+        ignore_unsupported(self.request_inlay_hints(lang_id, text_document.clone()))?;
+
+        Ok(())
+    }
+
+    // Request hints of the whole document with `textDocument/inlayHint`
+    fn request_inlay_hints(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+    ) -> Result<(), LspcError> {
+        let line_count = match self
+            .tracking_buffers
+            .values()
+            .find(|tracking_buf| tracking_buf.text_document.uri == text_document.uri)
+        {
+            Some(tracking_buf) => tracking_buf.line_count,
+            None => return Ok(()),
+        };
+        let handler =
+            self.handler_providing(lang_id, &text_document, "inlay hints", "inlayHintProvider")?;
+        let params = InlayHintParams {
+            text_document: text_document.clone(),
+            range: Range::new(Position::new(0, 0), Position::new(line_count, 0)),
+        };
+        handler.lsp_request::<InlayHintRequest>(
+            params,
+            Box::new(move |editor: &mut E, _handler, response| {
+                let hints = response
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(InlayHint::from)
+                    .collect::<Vec<_>>();
+                editor.inline_hints(&text_document, &hints)?;
+
+                Ok(())
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    // Request hints again for documents of the server at `index`
+    fn refresh_inlay_hints(&mut self, index: usize) -> Result<(), LspcError> {
+        let documents = self
+            .tracking_buffers
+            .values()
+            .filter(|tracking_buf| tracking_buf.sent_did_open)
+            .filter(|tracking_buf| {
+                let uri = &tracking_buf.text_document.uri;
+                handler_indices_of(&self.lsp_handlers, &tracking_buf.lang_id, uri).contains(&index)
+            })
+            .map(|tracking_buf| {
+                (
+                    tracking_buf.lang_id.clone(),
+                    tracking_buf.text_document.clone(),
                 )
-                .map_err(|e| LspcError::LangServer(e))?;
+            })
+            .collect::<Vec<_>>();
+        for (lang_id, text_document) in documents {
+            ignore_unsupported(self.request_inlay_hints(&lang_id, text_document))?;
+        }
+
+        Ok(())
+    }
+
+    // Request tokens of the whole document, as a delta of the previous
+    // ones if the server supports it
+    fn request_semantic_tokens(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+    ) -> Result<(), LspcError> {
+        let handler = self
+            .semantic_tokens_handler(lang_id, &text_document, |options| options.supports_full())?;
+        let supports_delta = handler
+            .semantic_tokens_options()
+            .map_or(false, |options| options.supports_delta());
+        let previous_result_id = handler
+            .semantic_tokens
+            .get(&text_document.uri)
+            .and_then(|tokens| tokens.result_id.clone());
+
+        match previous_result_id {
+            Some(previous_result_id) if supports_delta => {
+                let params = SemanticTokensDeltaParams {
+                    text_document: text_document.clone(),
+                    previous_result_id,
+                };
+                handler.lsp_request::<SemanticTokensFullDelta>(
+                    params,
+                    Box::new(move |editor: &mut E, handler, response| {
+                        let tokens = match response {
+                            Some(SemanticTokensFullDeltaResult::Tokens(tokens)) => tokens,
+                            Some(SemanticTokensFullDeltaResult::Delta(delta)) => {
+                                let previous = handler.semantic_tokens.remove(&text_document.uri);
+                                let mut tokens = match previous {
+                                    Some(tokens) => tokens,
+                                    None => return Ok(()),
+                                };
+                                tokens.apply_edits(delta.edits);
+                                tokens.result_id = delta.result_id;
+                                tokens
+                            }
+                            None => return Ok(()),
+                        };
+
+                        show_semantic_tokens(editor, handler, text_document, tokens)
+                    }),
+                )?;
+            }
+            _ => {
+                let params = SemanticTokensParams {
+                    text_document: text_document.clone(),
+                };
+                handler.lsp_request::<SemanticTokensFull>(
+                    params,
+                    Box::new(move |editor: &mut E, handler, response| {
+                        show_semantic_tokens(
+                            editor,
+                            handler,
+                            text_document,
+                            response.unwrap_or_default(),
+                        )
+                    }),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn request_document_colors(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+    ) -> Result<(), LspcError> {
+        let handler = self.handler_for(
+            lang_id,
+            &text_document,
+            "document color",
+            supports_document_color,
+        )?;
+        let params = DocumentColorParams {
+            text_document: text_document.clone(),
+        };
+        handler.lsp_request::<DocumentColor>(
+            params,
+            Box::new(move |editor: &mut E, handler, response| {
+                editor.show_document_colors(&text_document, &response)?;
+                handler.document_colors.insert(text_document.uri, response);
+
+                Ok(())
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    fn request_folding_ranges(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+    ) -> Result<(), LspcError> {
+        let handler = self.handler_for(
+            lang_id,
+            &text_document,
+            "folding range",
+            supports_folding_range,
+        )?;
+        let params = FoldingRangeParams {
+            text_document: text_document.clone(),
+        };
+        handler.lsp_request::<FoldingRangeRequest>(
+            params,
+            Box::new(move |editor: &mut E, _handler, response| {
+                editor.show_folding_ranges(&text_document, &response.unwrap_or_default())?;
+
+                Ok(())
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    fn request_code_lenses(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+    ) -> Result<(), LspcError> {
+        let handler = self.handler_for(lang_id, &text_document, "code lens", |c| {
+            c.code_lens_provider.is_some()
+        })?;
+        let params = CodeLensParams {
+            text_document: text_document.clone(),
+        };
+        handler.lsp_request::<CodeLensRequest>(
+            params,
+            Box::new(move |editor: &mut E, handler, response| {
+                show_code_lenses(editor, handler, text_document, response.unwrap_or_default())
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    fn handler_of_server(
+        &mut self,
+        server: &ServerKey,
+    ) -> Result<&mut LangServerHandler<E>, LspcError> {
+        self.lsp_handlers
+            .iter_mut()
+            .find(|handler| handler.key() == *server)
+            .ok_or(LspcError::NotStarted)
+    }
+
+    fn run_code_action(&mut self, item: CodeActionItem) -> Result<(), LspcError> {
+        let server = item.server;
+        let (action, edit) = match item.action {
+            CodeActionOrCommand::Command(command) => {
+                self.pending_commands.borrow_mut().push((server, command));
+                return Ok(());
+            }
+            CodeActionOrCommand::CodeAction(action) => (action, item.edit),
+        };
+        // The command goes with the edit
+        let then = match action.command {
+            Some(command) => AfterEdit::Command(server, command),
+            None => AfterEdit::Nothing,
+        };
+        let then = match edit {
+            Some(edit) => confirm_workspace_edit(
+                &mut self.editor,
+                &self.choices,
+                &action.title,
+                &edit,
+                item.preview,
+                then,
+            )?,
+            None => Some(then),
+        };
+        match then {
+            Some(then) => self.after_edit(then, Ok(true)),
+            None => Ok(()),
+        }
+    }
+
+    // Run the command or answer the request following an edit,
+    // `applied` is an error if it failed
+    fn after_edit(
+        &mut self,
+        then: AfterEdit,
+        applied: Result<bool, EditorError>,
+    ) -> Result<(), LspcError> {
+        match then {
+            AfterEdit::Nothing => {
+                applied?;
+            }
+            AfterEdit::Command(server, command) => {
+                if applied? {
+                    self.pending_commands.borrow_mut().push((server, command));
+                }
+            }
+            AfterEdit::Reply { server, request_id } => {
+                let result = match applied {
+                    Ok(applied) => ApplyWorkspaceEditResult {
+                        applied,
+                        failure_reason: None,
+                    },
+                    Err(e) => {
+                        log::error!("Failed to apply workspace edit: {:?}", e);
+                        ApplyWorkspaceEditResult {
+                            applied: false,
+                            failure_reason: Some(format!("{:?}", e)),
+                        }
+                    }
+                };
+                self.handler_of_server(&server)?
+                    .lsp_respond::<ApplyAnnotatedWorkspaceEdit>(request_id, result)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Apply the changes of a previewed edit which are `accepted`,
+    // nothing if it is cancelled
+    fn apply_previewed_edit(
+        &mut self,
+        changes: Vec<(usize, WorkspaceChange)>,
+        accepted: Option<Vec<usize>>,
+        then: AfterEdit,
+    ) -> Result<(), LspcError> {
+        let changes = accepted_changes(changes, &accepted.unwrap_or_default());
+        let applied = if changes.is_empty() {
+            Ok(false)
+        } else {
+            self.editor.apply_workspace_changes(&changes).map(|_| true)
+        };
+
+        self.after_edit(then, applied)
+    }
+
+    // Carry on with what was chosen by the user
+    fn choose(&mut self, id: u64, index: Option<usize>) -> Result<(), LspcError> {
+        let choice = match self.choices.borrow_mut().pending.remove(&id) {
+            Some(choice) => choice,
+            None => return Ok(()),
+        };
+        match choice {
+            Choice::CodeAction(actions) => {
+                let item = index.and_then(|index| actions.into_iter().nth(index));
+                if let Some(item) = item {
+                    self.run_code_action(item)?;
+                }
+            }
+            Choice::CodeLens { server, commands } => {
+                let command = index.and_then(|index| commands.into_iter().nth(index));
+                self.run_code_lens(server, command)?;
+            }
+            Choice::ColorPresentation {
+                uri,
+                range,
+                presentations,
+            } => {
+                let presentation = match index.and_then(|index| presentations.get(index)) {
+                    Some(presentation) => presentation,
+                    None => return Ok(()),
+                };
+                let mut edits = vec![presentation
+                    .text_edit
+                    .clone()
+                    .unwrap_or_else(|| TextEdit::new(range, presentation.label.clone()))];
+                edits.extend(presentation.additional_text_edits.iter().flatten().cloned());
+                let mut changes = HashMap::new();
+                changes.insert(uri, edits);
+                self.editor
+                    .apply_workspace_edit(&WorkspaceEdit::new(changes))?;
+            }
+            Choice::MessageAction {
+                server,
+                request_id,
+                actions,
+            } => {
+                let action = index.and_then(|index| actions.into_iter().nth(index));
+                self.handler_of_server(&server)?
+                    .lsp_respond::<ShowMessageRequest>(request_id, action)?;
+            }
+            Choice::Runnable {
+                runnables,
+                background,
+            } => {
+                let runnable = index.and_then(|index| runnables.into_iter().nth(index));
+                if let Some(runnable) = runnable {
+                    run_runnable(&mut self.editor, &runnable, background)?;
+                }
+            }
+            // Answered with `Event::Previewed`, cancelled otherwise
+            Choice::WorkspaceEdit { changes, then } => {
+                self.apply_previewed_edit(changes, None, then)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_code_lens(
+        &mut self,
+        server: ServerKey,
+        command: Option<lsp::Command>,
+    ) -> Result<(), LspcError> {
+        if let Some(command) = command {
+            if !run_client_command(&mut self.editor, &command)? {
+                self.pending_commands.borrow_mut().push((server, command));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expand_tree_node(&mut self, tree: String, node_id: u64) -> Result<(), LspcError> {
+        let node = match self.trees.borrow().get(&tree, node_id) {
+            Some(node) => node.clone(),
+            None => return Ok(()),
+        };
+        match node {
+            TreeNode::Calls {
+                server,
+                item,
+                incoming,
+            } => self.expand_calls(tree, node_id, &server, item, incoming),
+            TreeNode::Types {
+                server,
+                item,
+                supertypes,
+            } => {
+                let trees = Rc::clone(&self.trees);
+                let handler = self.handler_of_server(&server)?;
+                request_type_hierarchy(
+                    handler,
+                    item,
+                    supertypes,
+                    Box::new(move |editor: &mut E, server, items| {
+                        // The tree shows another hierarchy now
+                        if trees.borrow().get(&tree, node_id).is_none() {
+                            return Ok(());
+                        }
+                        let items =
+                            type_hierarchy_tree_items(&trees, &tree, server, items, supertypes);
+                        editor.show_tree_children(&tree, node_id, &items)?;
+
+                        Ok(())
+                    }),
+                )
+            }
+        }
+    }
+
+    fn expand_calls(
+        &mut self,
+        tree: String,
+        node_id: u64,
+        server: &ServerKey,
+        item: CallHierarchyItem,
+        incoming: bool,
+    ) -> Result<(), LspcError> {
+        let trees = Rc::clone(&self.trees);
+        let handler = self.handler_of_server(server)?;
+
+        let params = CallHierarchyCallsParams { item: item.clone() };
+        if incoming {
+            handler.lsp_request::<CallHierarchyIncomingCalls>(
+                params,
+                Box::new(move |editor: &mut E, handler, response| {
+                    if trees.borrow().get(&tree, node_id).is_none() {
+                        return Ok(());
+                    }
+                    let server = handler.key();
+                    let items = response
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|call| {
+                            let range = call
+                                .from_ranges
+                                .first()
+                                .copied()
+                                .unwrap_or(call.from.selection_range);
+                            let text = hierarchy_item_text(&call.from);
+                            let location = Location::new(call.from.uri.clone(), range);
+                            let node = TreeNode::Calls {
+                                server: server.clone(),
+                                item: call.from,
+                                incoming,
+                            };
+                            tree_item(&trees, &tree, text, location, node)
+                        })
+                        .collect::<Vec<_>>();
+                    editor.show_tree_children(&tree, node_id, &items)?;
+
+                    Ok(())
+                }),
+            )?;
+        } else {
+            handler.lsp_request::<CallHierarchyOutgoingCalls>(
+                params,
+                Box::new(move |editor: &mut E, handler, response| {
+                    if trees.borrow().get(&tree, node_id).is_none() {
+                        return Ok(());
+                    }
+                    let server = handler.key();
+                    let items = response
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|call| {
+                            // Jump to the call in the caller, or to the callee
+                            let location = match call.from_ranges.first() {
+                                Some(range) => Location::new(item.uri.clone(), *range),
+                                None => Location::new(call.to.uri.clone(), call.to.selection_range),
+                            };
+                            let text = hierarchy_item_text(&call.to);
+                            let node = TreeNode::Calls {
+                                server: server.clone(),
+                                item: call.to,
+                                incoming,
+                            };
+                            tree_item(&trees, &tree, text, location, node)
+                        })
+                        .collect::<Vec<_>>();
+                    editor.show_tree_children(&tree, node_id, &items)?;
+
+                    Ok(())
+                }),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Send formatting request for a buffer being written, false if
+    // nothing is requested and the editor can be replied now
+    fn format_on_save(
+        &mut self,
+        request_id: u64,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+    ) -> Result<bool, LspcError> {
+        self.sync_document(&text_document)?;
+        let pending_replies = Rc::clone(&self.pending_replies);
+        let handler = self.handler_for(lang_id, &text_document, "formatting", |c| {
+            c.document_formatting_provider == Some(true)
+        })?;
+        if !handler.lang_settings.format_on_save {
+            return Ok(false);
+        }
+
+        let uri = text_document.uri.clone();
+        let params = DocumentFormattingParams {
+            text_document,
+            options: formatting_options(&handler.lang_settings),
+        };
+        let replies = Rc::clone(&pending_replies);
+        let lsp_request_id = handler.lsp_request::<Formatting>(
+            params,
+            Box::new(move |editor: &mut E, _handler, response| {
+                replies
+                    .borrow_mut()
+                    .retain(|pending| pending.request_id != request_id);
+
+                let mut changes = HashMap::new();
+                changes.insert(uri, response.unwrap_or_default());
+                let result = editor.apply_workspace_edit(&WorkspaceEdit::new(changes));
+                let error = result.as_ref().err().map(|_| "failed to apply formatting");
+                editor.reply(request_id, error)?;
+
+                result.map_err(LspcError::Editor)
+            }),
+        )?;
+        pending_replies.borrow_mut().push(PendingReply {
+            request_id,
+            server: handler.key(),
+            lsp_request_id,
+            deadline: Instant::now() + Duration::from_millis(FORMAT_ON_SAVE_TIMEOUT_MS),
+        });
+
+        Ok(true)
+    }
+
+    // Reply with an error to editor requests whose server request
+    // has failed or timed out
+    fn expire_pending_replies(&mut self, now: Instant) -> Result<(), LspcError> {
+        let pending_replies =
+            std::mem::replace(&mut *self.pending_replies.borrow_mut(), Vec::new());
+        for pending in pending_replies {
+            let handler = self
+                .lsp_handlers
+                .iter_mut()
+                .find(|handler| handler.key() == pending.server);
+            let error = match handler {
+                Some(handler) if handler.is_pending(pending.lsp_request_id) => {
+                    if pending.deadline > now {
+                        self.pending_replies.borrow_mut().push(pending);
+                        continue;
+                    }
+                    handler.cancel_request(pending.lsp_request_id)?;
+                    "server request timed out"
+                }
+                _ => "server request failed",
+            };
+            self.editor.reply(pending.request_id, Some(error))?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_editor_event(&mut self, event: Event<E::BufferId>) -> Result<(), LspcError> {
+        match event {
+            Event::Hello => {
+                self.editor.say_hello().map_err(|e| LspcError::Editor(e))?;
+            }
+            Event::StartServer {
+                lang_id,
+                config,
+                cur_path,
+            } => {
+                let capabilities = self.editor.capabilities();
+                let lang_settings = LangSettings {
+                    root_markers: config.root_markers.clone(),
+                    indentation: config.indentation,
+                    indentation_with_space: config.indentation_with_space,
+                    format_on_save: config.format_on_save,
+                    preview_edits: config.preview_edits,
+                    log_level: config
+                        .log_level
+                        .as_ref()
+                        .and_then(|level| LogLevel::parse(level))
+                        .unwrap_or(LogLevel::Info),
+                };
+
+                let cur_path = PathBuf::from(cur_path);
+                let root = find_root_path(&cur_path, &config.root_markers)
+                    .map(|path| path.to_str())
+                    .ok_or_else(|| LspcError::Editor(EditorError::RootPathNotFound))?
+                    .ok_or_else(|| LspcError::Editor(EditorError::RootPathNotFound))?;
+
+                let root_url =
+                    to_file_url(&root).ok_or(LspcError::Editor(EditorError::RootPathNotFound))?;
+
+                let name = config.name().to_owned();
+                let started = self.lsp_handlers.iter().any(|handler| {
+                    handler.name == name
+                        && handler.root_of(Path::new(root)).as_deref() == Some(Path::new(root))
+                });
+                if started {
+                    self.editor
+                        .message(&format!("{} is already started at {}", name, root))?;
+                    return Ok(());
+                }
+
+                // Add new root to a running server if it supports workspace folders
+                let running = self.lsp_handlers.iter_mut().find(|handler| {
+                    handler.lang_id == lang_id
+                        && handler.name == name
+                        && handler.supports_workspace_folders()
+                });
+                if let Some(handler) = running {
+                    let folder = to_workspace_folder(root)
+                        .ok_or(LspcError::Editor(EditorError::RootPathNotFound))?;
+                    handler.add_workspace_folder(folder)?;
+                    self.editor
+                        .message(&format!("Added workspace folder {} to {}", root, name))?;
+                    return Ok(());
+                }
+
+                let mut lsp_handler = LangServerHandler::new(
+                    lang_id,
+                    name,
+                    &config.command[0],
+                    lang_settings,
+                    &config.command[1..],
+                    root.to_owned(),
+                )
+                .map_err(|e| LspcError::LangServer(e))?;
+                lsp_handler.settings = config.settings.clone().unwrap_or(Value::Null);
+
+                let init_params = lsp_types::InitializeParams {
+                    process_id: Some(std::process::id() as u64),
+                    root_path: Some(root.into()),
+                    root_uri: Some(root_url),
+                    initialization_options: config.initialization_options.clone(),
+                    capabilities,
+                    trace: None,
+                    workspace_folders: Some(lsp_handler.workspace_folders().to_vec()),
+                };
+                // Capabilities unknown to `lsp_types`
+                let mut init_params = serde_json::to_value(init_params)
+                    .map_err(|e| EditorError::Failed(format!("{}", e)))?;
+                if let Some(capabilities) = init_params.get_mut("capabilities") {
+                    extend_capabilities(capabilities, client_capabilities());
+                }
+                lsp_handler.lsp_request::<RawInitialize>(
+                    init_params,
+                    Box::new(|editor: &mut E, handler, response| {
+                        handler.initialize_response(response)?;
+
+                        let (triggers, retriggers) = handler.signature_help_characters();
+                        editor.set_trigger_characters(
+                            &handler.lang_id,
+                            "signature_help",
+                            &triggers,
+                        )?;
+                        editor.set_trigger_characters(
+                            &handler.lang_id,
+                            "signature_help_retrigger",
+                            &retriggers,
+                        )?;
+                        editor.set_trigger_characters(
+                            &handler.lang_id,
+                            "on_type_formatting",
+                            &handler.on_type_formatting_characters(),
+                        )?;
+                        editor
+                            .set_server_commands(&handler.lang_id, &handler.execute_commands())?;
+                        // Servers which don't ask for configuration expect it pushed
+                        if !handler.settings.is_null() {
+                            handler.notify_configuration()?;
+                        }
+
+                        editor.message("LangServer initialized")?;
+                        Ok(())
+                    }),
+                )?;
+
+                self.lsp_handlers.push(lsp_handler);
+            }
+            Event::Hover {
+                lang_id,
+                text_document,
+                position,
+            } => {
+                let handler = self.handler_for(&lang_id, &text_document, "hover", |c| {
+                    c.hover_provider == Some(true)
+                })?;
+                let text_document_clone = text_document.clone();
+                let params = lsp_types::TextDocumentPositionParams {
+                    text_document,
+                    position,
+                };
+                handler.lsp_request::<HoverRequest>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(hover) = response {
+                            editor.show_hover(&text_document_clone, &hover)?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::SignatureHelp {
+                lang_id,
+                text_document,
+                position,
+            } => {
+                self.sync_document(&text_document)?;
+                let handler =
+                    self.handler_for(&lang_id, &text_document, "signature help", |c| {
+                        c.signature_help_provider.is_some()
+                    })?;
+                let text_document_clone = text_document.clone();
+                let params = lsp_types::TextDocumentPositionParams {
+                    text_document,
+                    position,
+                };
+                handler.lsp_request::<SignatureHelpRequest>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(signature_help) = response {
+                            editor.show_signature_help(&text_document_clone, &signature_help)?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::DocumentHighlight {
+                lang_id,
+                text_document,
+                position,
+            } => {
+                if let Some((key, id)) = self.document_highlight.take() {
+                    let handler = self.lsp_handlers.iter_mut().find(|h| h.key() == key);
+                    if let Some(handler) = handler {
+                        handler.cancel_request(id)?;
+                    }
+                }
+
+                self.sync_document(&text_document)?;
+                let handler =
+                    match self.handler_for(&lang_id, &text_document, "document highlight", |c| {
+                        c.document_highlight_provider == Some(true)
+                    }) {
+                        // Requested on every cursor hold, don't report it
+                        Err(LspcError::NotSupported(_)) => return Ok(()),
+                        handler => handler?,
+                    };
+                let key = handler.key();
+                let text_document_clone = text_document.clone();
+                let params = lsp_types::TextDocumentPositionParams {
+                    text_document,
+                    position,
+                };
+                let id = handler.lsp_request::<DocumentHighlightRequest>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        editor.show_document_highlights(
+                            &text_document_clone,
+                            &response.unwrap_or_default(),
+                        )?;
+
+                        Ok(())
+                    }),
+                )?;
+                self.document_highlight = Some((key, id));
+            }
+            Event::GotoDefinition {
+                lang_id,
+                text_document,
+                position,
+            } => {
+                let handler = self.handler_for(&lang_id, &text_document, "definition", |c| {
+                    c.definition_provider == Some(true)
+                })?;
+                let params = lsp_types::TextDocumentPositionParams {
+                    text_document,
+                    position,
+                };
+                handler.lsp_request::<GotoDefinition>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(definition) = response {
+                            match definition {
+                                GotoDefinitionResponse::Scalar(location) => {
+                                    editor.goto(&location)?;
+                                }
+                                GotoDefinitionResponse::Array(array) => {
+                                    if array.len() == 1 {
+                                        editor.goto(&array[0])?;
+                                    }
+                                }
+                                _ => {
+                                    // FIXME: support Array & Link
+                                }
+                            }
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::InlayHints {
+                lang_id,
+                text_document,
+            } => {
+                match self.request_inlay_hints(&lang_id, text_document.clone()) {
+                    Err(LspcError::NotSupported(_)) => {}
+                    result => return result,
+                }
+
+                // Fall back to the hints of rust-analyzer before they were standard
+                let handler =
+                    self.rust_analyzer_handler(&lang_id, &text_document, "inlay hints")?;
+                let text_document_clone = text_document.clone();
+                let params = InlayHintsParams { text_document };
+                handler.lsp_request::<InlayHints>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        editor.inline_hints(&text_document_clone, &response)?;
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::DocumentSymbol {
+                lang_id,
+                text_document,
+            } => {
+                let handler =
+                    self.handler_for(&lang_id, &text_document, "document symbol", |c| {
+                        c.document_symbol_provider == Some(true)
+                    })?;
+                let uri = text_document.uri.clone();
+                let params = DocumentSymbolParams { text_document };
+                handler.lsp_request::<DocumentSymbolRequest>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        let items = match response {
+                            Some(DocumentSymbolResponse::Flat(symbols)) => symbols
+                                .iter()
+                                .map(symbol_information_item)
+                                .collect::<Vec<_>>(),
+                            Some(DocumentSymbolResponse::Nested(symbols)) => {
+                                let mut items = Vec::new();
+                                document_symbol_items(&uri, &symbols, 0, &mut items);
+                                items
+                            }
+                            None => Vec::new(),
+                        };
+                        editor.show_symbols("Document symbols", &items)?;
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::WorkspaceSymbol {
+                lang_id,
+                text_document,
+                query,
+            } => {
+                let handler =
+                    self.handler_for(&lang_id, &text_document, "workspace symbol", |c| {
+                        c.workspace_symbol_provider == Some(true)
+                    })?;
+                let title = format!("Workspace symbols: {}", query);
+                let params = WorkspaceSymbolParams { query };
+                handler.lsp_request::<WorkspaceSymbol>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        let items = response
+                            .unwrap_or_default()
+                            .iter()
+                            .map(symbol_information_item)
+                            .collect::<Vec<_>>();
+                        editor.show_symbols(&title, &items)?;
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::Capabilities { lang_id } => {
+                let mut lines = Vec::new();
+                for handler in self.lsp_handlers.iter() {
+                    if handler.lang_id != lang_id {
+                        continue;
+                    }
+                    let capabilities = match handler.raw_capabilities() {
+                        Some(capabilities) => capabilities,
+                        None => continue,
+                    };
+
+                    lines.push(format!("// {} ({})", handler.name, handler.root_path()));
+                    lines.extend(
+                        serde_json::to_string_pretty(capabilities)
+                            .map_err(|e| EditorError::Failed(format!("{}", e)))?
+                            .lines()
+                            .map(String::from),
+                    );
+                }
+                if lines.is_empty() {
+                    return Err(LspcError::NotStarted);
+                }
+
+                self.editor.show_scratch(
+                    &format!("__LspcCapabilities_{}__", lang_id),
+                    &lines,
+                    Some("json"),
+                )?;
+            }
+            Event::FormatDoc {
+                lang_id,
+                text_document_lines,
+                text_document,
+            } => {
+                let handler = self.handler_for(&lang_id, &text_document, "formatting", |c| {
+                    c.document_formatting_provider == Some(true)
+                })?;
+                let params = DocumentFormattingParams {
+                    text_document,
+                    options: formatting_options(&handler.lang_settings),
+                };
+                handler.lsp_request::<Formatting>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(edits) = response {
+                            editor.apply_edits(&text_document_lines, &edits)?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::FormatOnSave {
+                request_id,
+                lang_id,
+                text_document,
+            } => match self.format_on_save(request_id, &lang_id, text_document) {
+                Ok(true) => {}
+                Ok(false) | Err(LspcError::NotStarted) | Err(LspcError::NotSupported(_)) => {
+                    self.editor.reply(request_id, None)?;
+                }
+                Err(e) => {
+                    self.editor
+                        .reply(request_id, Some("format on save failed"))?;
+                    return Err(e);
+                }
+            },
+            Event::FormatRange {
+                lang_id,
+                text_document_lines,
+                text_document,
+                range,
+            } => {
+                self.sync_document(&text_document)?;
+                let handler =
+                    self.handler_for(&lang_id, &text_document, "range formatting", |c| {
+                        c.document_range_formatting_provider == Some(true)
+                    })?;
+                let params = DocumentRangeFormattingParams {
+                    text_document,
+                    range,
+                    options: formatting_options(&handler.lang_settings),
+                };
+                handler.lsp_request::<RangeFormatting>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(edits) = response {
+                            editor.apply_edits(&text_document_lines, &edits)?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::FormatOnType {
+                lang_id,
+                text_document_lines,
+                text_document,
+                position,
+                ch,
+            } => {
+                self.sync_document(&text_document)?;
+                let handler =
+                    self.handler_for(&lang_id, &text_document, "on type formatting", |c| {
+                        c.document_on_type_formatting_provider.is_some()
+                    })?;
+                let params = DocumentOnTypeFormattingParams {
+                    text_document_position: lsp_types::TextDocumentPositionParams {
+                        text_document,
+                        position,
+                    },
+                    ch,
+                    options: formatting_options(&handler.lang_settings),
+                };
+                handler.lsp_request::<OnTypeFormatting>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(edits) = response {
+                            editor.apply_edits(&text_document_lines, &edits)?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::CodeAction {
+                lang_id,
+                text_document,
+                range,
+            } => {
+                let indices = handler_indices_of(&self.lsp_handlers, &lang_id, &text_document.uri);
+                if indices.is_empty() {
+                    return Err(LspcError::NotStarted);
+                }
+                let indices = indices
+                    .into_iter()
+                    .filter(|index| {
+                        self.lsp_handlers[*index]
+                            .ensure_supports("code action", supports_code_action)
+                            .is_ok()
+                    })
+                    .collect::<Vec<_>>();
+                if indices.is_empty() {
+                    return Err(LspcError::NotSupported("code action"));
+                }
+
+                let merge = Rc::new(RefCell::new(CodeActionMerge {
+                    remaining: indices.len(),
+                    actions: Vec::new(),
+                }));
+                for index in indices {
+                    let handler = &mut self.lsp_handlers[index];
+                    let diagnostics = self
+                        .diagnostics
+                        .get(&text_document.uri)
+                        .and_then(|by_server| by_server.get(&handler.key()))
+                        .map(|diagnostics| {
+                            diagnostics
+                                .iter()
+                                .filter(|d| {
+                                    d.range.start.line <= range.end.line
+                                        && range.start.line <= d.range.end.line
+                                })
+                                .cloned()
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+                    let params = CodeActionParams {
+                        text_document: text_document.clone(),
+                        range,
+                        context: CodeActionContext {
+                            diagnostics,
+                            only: None,
+                        },
+                    };
+
+                    let merge = Rc::clone(&merge);
+                    let choices = Rc::clone(&self.choices);
+                    handler.lsp_request_result::<RawCodeActions>(
+                        params,
+                        Box::new(move |editor: &mut E, handler, response| {
+                            let actions = response.map(|response| {
+                                code_action_items(handler, response.unwrap_or_default())
+                            });
+                            let actions = match merge.borrow_mut().add(actions) {
+                                Some(actions) => actions,
+                                None => return Ok(()),
+                            };
+                            select_code_action(editor, &choices, actions)
+                        }),
+                    )?;
+                }
+            }
+            Event::DidChangeConfiguration { lang_id, config } => {
+                let settings = config.settings.clone().unwrap_or(Value::Null);
+                let handlers = self
+                    .lsp_handlers
+                    .iter_mut()
+                    .filter(|handler| handler.lang_id == lang_id && handler.name == config.name());
+                for handler in handlers {
+                    handler.update_settings(settings.clone())?;
+                }
+            }
+            Event::DidOpen {
+                lang_id,
+                buf_id,
+                text_document,
+            } => {
+                // Opened on every server of the document once its text is sent
+                let mut managed =
+                    !handler_indices_of(&self.lsp_handlers, &lang_id, &text_document.uri)
+                        .is_empty();
+                if !managed {
+                    self.add_workspace_folder_of(&lang_id, &text_document.uri)?;
+                    managed = !handler_indices_of(&self.lsp_handlers, &lang_id, &text_document.uri)
+                        .is_empty();
+                }
+                if !managed {
+                    log::info!("Unmanaged file: {:?}", text_document.uri);
+                    return Err(MainLoopError::IgnoredMessage.into());
+                }
+
+                self.editor.watch_file_events(&text_document)?;
+                self.tracking_buffers
+                    .insert(buf_id, TrackingBuffer::new(lang_id, text_document.clone()));
+            }
+            Event::DidChange {
+                buf_id,
+                version,
+                content_change,
+                line_count,
+            } => {
+                if let Some(tracking_buf) = self.tracking_buffers.get(&buf_id) {
+                    self.selection_chains
+                        .borrow_mut()
+                        .remove(&tracking_buf.text_document.uri);
+                }
+                let (handlers, tracking_buf) =
+                    self.handlers_for_buffer(&buf_id).ok_or_else(|| {
+                        log::info!(
+                            "Received changed event for nontracking buffer: {:?}",
+                            buf_id
+                        );
+                        MainLoopError::IgnoredMessage
+                    })?;
+
+                if !tracking_buf.sent_did_open {
+                    tracking_buf.update_line_count(None, line_count);
+                    for handler in handlers {
+                        handler.lsp_notify::<noti::DidOpenTextDocument>(
+                            lsp::DidOpenTextDocumentParams {
+                                text_document: lsp::TextDocumentItem {
+                                    uri: tracking_buf.text_document.uri.clone(),
+                                    language_id: tracking_buf.lang_id.clone(),
+                                    version,
+                                    text: content_change.text.clone(),
+                                },
+                            },
+                        )?;
+                    }
+                    tracking_buf.sent_did_open = true;
+
+                    let lang_id = tracking_buf.lang_id.clone();
+                    let text_document = tracking_buf.text_document.clone();
+                    self.refresh_document(&lang_id, &text_document)?;
+                } else {
+                    tracking_buf.update_line_count(content_change.range, line_count);
+                    tracking_buf.delay_sync_in(
+                        Duration::from_millis(SYNC_DELAY_MS),
+                        version,
+                        content_change,
+                    );
+                }
+            }
+            Event::DidClose { buf_id } => {
+                let (mut handlers, tracking_buf) =
+                    self.handlers_for_buffer(&buf_id).ok_or_else(|| {
+                        log::info!(
+                            "Received changed event for nontracking buffer: {:?}",
+                            buf_id
+                        );
+                        MainLoopError::IgnoredMessage
+                    })?;
+
+                tracking_buf.sync_pending_changes(&mut handlers)?;
+                for handler in handlers {
+                    handler.lsp_notify::<noti::DidCloseTextDocument>(
+                        lsp::DidCloseTextDocumentParams {
+                            text_document: tracking_buf.text_document.clone(),
+                        },
+                    )?;
+                    handler
+                        .semantic_tokens
+                        .remove(&tracking_buf.text_document.uri);
+                }
+            }
+            Event::DidSave { buf_id } => {
+                let (mut handlers, tracking_buf) =
+                    self.handlers_for_buffer(&buf_id).ok_or_else(|| {
+                        log::info!("Received saved event for nontracking buffer: {:?}", buf_id);
+                        MainLoopError::IgnoredMessage
+                    })?;
+
+                tracking_buf.sync_pending_changes(&mut handlers)?;
+                let text_document = tracking_buf.text_document.clone();
+                let lang_id = tracking_buf.lang_id.clone();
+                for handler in handlers {
+                    handler.lsp_notify::<noti::DidSaveTextDocument>(
+                        lsp::DidSaveTextDocumentParams {
+                            text_document: text_document.clone(),
+                        },
+                    )?;
+                }
+
+                // Lenses like reference counts change on save
+                match self.request_code_lenses(&lang_id, text_document) {
+                    Err(LspcError::NotSupported(_)) => {}
+                    result => result?,
+                }
+            }
+            Event::CodeLens {
+                lang_id,
+                text_document,
+            } => {
+                self.request_code_lenses(&lang_id, text_document)?;
+            }
+            Event::CodeLensAction {
+                lang_id,
+                text_document,
+                position,
+            } => {
+                let handler = self.handler_for(&lang_id, &text_document, "code lens", |c| {
+                    c.code_lens_provider.is_some()
+                })?;
+                let server = handler.key();
+                let commands = handler
+                    .code_lenses
+                    .get(&text_document.uri)
+                    .map(|lenses| {
+                        lenses
+                            .iter()
+                            .filter(|lens| lens.range.start.line == position.line)
+                            .filter_map(|lens| lens.command.clone())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                match commands.len() {
+                    0 => {
+                        self.editor.message("No code lens on this line")?;
+                    }
+                    1 => {
+                        let command = commands.into_iter().next();
+                        self.run_code_lens(server, command)?;
+                    }
+                    _ => {
+                        let titles = commands
+                            .iter()
+                            .map(|command| command.title.clone())
+                            .collect::<Vec<_>>();
+                        ask_choice(
+                            &mut self.editor,
+                            &self.choices,
+                            "Select code lens:",
+                            &titles,
+                            Choice::CodeLens { server, commands },
+                        )?;
+                    }
+                }
+            }
+            Event::RustAnalyzer {
+                lang_id,
+                text_document,
+                extension,
+                range,
+            } => {
+                self.request_extension(&lang_id, text_document, extension, range)?;
+            }
+            Event::Ssr {
+                lang_id,
+                text_document,
+                query,
+                position,
+                scope,
+            } => {
+                self.request_ssr(&lang_id, text_document, query, position, scope)?;
+            }
+            Event::Runnables {
+                lang_id,
+                text_document,
+                position,
+                background,
+            } => {
+                self.request_runnables(&lang_id, text_document, position, background)?;
+            }
+            Event::ExecuteCommand {
+                lang_id,
+                text_document,
+                command,
+                arguments,
+            } => {
+                let handler =
+                    self.handler_for(&lang_id, &text_document, "execute command", |c| {
+                        c.execute_command_provider.is_some()
+                    })?;
+                let params = ExecuteCommandParams {
+                    command: command.clone(),
+                    arguments,
+                };
+                handler.lsp_request::<ExecuteCommand>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        // Edits come separately as `workspace/applyEdit` requests
+                        match response {
+                            Some(Value::Null) | None => {
+                                editor.message(&format!("{} done", command))?
+                            }
+                            Some(result) => {
+                                let lines = serde_json::to_string_pretty(&result)
+                                    .map_err(|e| EditorError::Failed(format!("{}", e)))?
+                                    .lines()
+                                    .map(String::from)
+                                    .collect::<Vec<_>>();
+                                editor.show_scratch(
+                                    "__LspcCommandResult__",
+                                    &lines,
+                                    Some("json"),
+                                )?;
+                            }
+                        }
 
-                let init_params = lsp_types::InitializeParams {
-                    process_id: Some(std::process::id() as u64),
-                    root_path: Some(root.into()),
-                    root_uri: Some(root_url),
-                    initialization_options: None,
-                    capabilities,
-                    trace: None,
-                    workspace_folders: None,
+                        Ok(())
+                    }),
+                )?;
+            }
+            Event::Rename {
+                lang_id,
+                text_document,
+                position,
+                new_name,
+            } => {
+                self.sync_document(&text_document)?;
+                let choices = Rc::clone(&self.choices);
+                let handler =
+                    self.handler_providing(&lang_id, &text_document, "rename", "renameProvider")?;
+                let params = RenameParams {
+                    text_document_position: lsp_types::TextDocumentPositionParams {
+                        text_document,
+                        position,
+                    },
+                    new_name: new_name.clone(),
                 };
-                lsp_handler.lsp_request::<Initialize>(
-                    init_params,
-                    Box::new(|editor: &mut E, handler, response| {
-                        handler.initialize_response(response)?;
+                handler.lsp_request::<RawRename>(
+                    params,
+                    Box::new(move |editor: &mut E, handler, response| {
+                        let edit = match response {
+                            Some(edit) => AnnotatedWorkspaceEdit::from_value(edit)
+                                .map_err(|e| EditorError::Failed(format!("{}", e)))?,
+                            None => {
+                                editor.message("Nothing to rename at cursor")?;
+                                return Ok(());
+                            }
+                        };
+                        // Previewed like edits of code actions
+                        let title = format!("Rename to {}", new_name);
+                        confirm_workspace_edit(
+                            editor,
+                            &choices,
+                            &title,
+                            &edit,
+                            handler.lang_settings.preview_edits,
+                            AfterEdit::Nothing,
+                        )?;
 
-                        editor.message("LangServer initialized")?;
                         Ok(())
                     }),
                 )?;
+            }
+            Event::ExpandSelection {
+                lang_id,
+                text_document,
+                range,
+            } => {
+                // Keep expanding the last chain while its selection is unchanged
+                if let Some(chain) = self
+                    .selection_chains
+                    .borrow_mut()
+                    .get_mut(&text_document.uri)
+                {
+                    if chain.ranges.get(chain.selected) == Some(&range) {
+                        if chain.selected + 1 < chain.ranges.len() {
+                            chain.selected += 1;
+                        }
+                        self.editor
+                            .set_selection(&text_document, &chain.ranges[chain.selected])?;
+                        return Ok(());
+                    }
+                }
+
+                self.sync_document(&text_document)?;
+                let selection_chains = Rc::clone(&self.selection_chains);
+                let handler = self.handler_for(
+                    &lang_id,
+                    &text_document,
+                    "selection range",
+                    supports_selection_range,
+                )?;
+                let params = SelectionRangeParams {
+                    text_document: text_document.clone(),
+                    positions: vec![range.start],
+                };
+                handler.lsp_request::<SelectionRangeRequest>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        let ranges = match response.unwrap_or_default().into_iter().next() {
+                            Some(selection_range) => selection_ranges(selection_range),
+                            None => return Ok(()),
+                        };
+                        let selected = ranges.iter().position(|selection| {
+                            *selection != range && range_contains(selection, &range)
+                        });
+                        let selected = match selected {
+                            Some(selected) => selected,
+                            None => return Ok(()),
+                        };
+                        editor.set_selection(&text_document, &ranges[selected])?;
+                        selection_chains
+                            .borrow_mut()
+                            .insert(text_document.uri, SelectionChain { ranges, selected });
 
-                self.lsp_handlers.push(lsp_handler);
+                        Ok(())
+                    }),
+                )?;
             }
-            Event::Hover {
+            Event::CallHierarchy {
                 lang_id,
                 text_document,
                 position,
+                incoming,
             } => {
-                let handler = self.handler_for(&lang_id).ok_or(LspcError::NotStarted)?;
-                let text_document_clone = text_document.clone();
+                let trees = Rc::clone(&self.trees);
+                let handler = self.handler_providing(
+                    &lang_id,
+                    &text_document,
+                    "call hierarchy",
+                    "callHierarchyProvider",
+                )?;
                 let params = lsp_types::TextDocumentPositionParams {
                     text_document,
                     position,
                 };
-                handler.lsp_request::<HoverRequest>(
+                handler.lsp_request::<CallHierarchyPrepare>(
                     params,
-                    Box::new(move |editor: &mut E, _handler, response| {
-                        if let Some(hover) = response {
-                            editor.show_hover(&text_document_clone, &hover)?;
+                    Box::new(move |editor: &mut E, handler, response| {
+                        let items = response.unwrap_or_default();
+                        if items.is_empty() {
+                            editor.message("No call hierarchy item at cursor")?;
+                            return Ok(());
                         }
 
+                        let tree = if incoming {
+                            "__LspcIncomingCalls__"
+                        } else {
+                            "__LspcOutgoingCalls__"
+                        };
+                        trees.borrow_mut().clear(tree);
+                        let server = handler.key();
+                        let items = items
+                            .into_iter()
+                            .map(|item| {
+                                let text = hierarchy_item_text(&item);
+                                let location =
+                                    Location::new(item.uri.clone(), item.selection_range);
+                                let node = TreeNode::Calls {
+                                    server: server.clone(),
+                                    item,
+                                    incoming,
+                                };
+                                tree_item(&trees, tree, text, location, node)
+                            })
+                            .collect::<Vec<_>>();
+                        editor.show_tree(tree, &items)?;
+
                         Ok(())
                     }),
                 )?;
             }
-            Event::GotoDefinition {
+            Event::TypeHierarchy {
                 lang_id,
                 text_document,
                 position,
+                supertypes,
+                flat,
             } => {
-                let handler = self.handler_for(&lang_id).ok_or(LspcError::NotStarted)?;
+                let trees = Rc::clone(&self.trees);
+                let handler = self.handler_providing(
+                    &lang_id,
+                    &text_document,
+                    "type hierarchy",
+                    "typeHierarchyProvider",
+                )?;
                 let params = lsp_types::TextDocumentPositionParams {
                     text_document,
                     position,
                 };
-                handler.lsp_request::<GotoDefinition>(
+                handler.lsp_request::<TypeHierarchyPrepare>(
                     params,
-                    Box::new(move |editor: &mut E, _handler, response| {
-                        if let Some(definition) = response {
-                            match definition {
-                                GotoDefinitionResponse::Scalar(location) => {
-                                    editor.goto(&location)?;
-                                }
-                                GotoDefinitionResponse::Array(array) => {
-                                    if array.len() == 1 {
-                                        editor.goto(&array[0])?;
-                                    }
-                                }
-                                _ => {
-                                    // FIXME: support Array & Link
-                                }
-                            }
+                    Box::new(move |editor: &mut E, handler, response| {
+                        let items = response.unwrap_or_default();
+                        if items.is_empty() {
+                            editor.message("No type hierarchy item at cursor")?;
+                            return Ok(());
                         }
 
-                        Ok(())
+                        let (title, tree) = if supertypes {
+                            ("Supertypes", "__LspcSupertypes__")
+                        } else {
+                            ("Subtypes", "__LspcSubtypes__")
+                        };
+                        if !flat {
+                            trees.borrow_mut().clear(tree);
+                            let items = type_hierarchy_tree_items(
+                                &trees,
+                                tree,
+                                handler.key(),
+                                items,
+                                supertypes,
+                            );
+                            editor.show_tree(tree, &items)?;
+                            return Ok(());
+                        }
+
+                        // Direct ones of the first item, in the location list
+                        let item = items.into_iter().next().unwrap();
+                        request_type_hierarchy(
+                            handler,
+                            item,
+                            supertypes,
+                            Box::new(move |editor: &mut E, _server, items| {
+                                let symbols = items
+                                    .iter()
+                                    .map(|item| {
+                                        let location =
+                                            Location::new(item.uri.clone(), item.selection_range);
+                                        (hierarchy_item_text(item), location)
+                                    })
+                                    .collect::<Vec<_>>();
+                                editor.show_symbols(title, &symbols)?;
+
+                                Ok(())
+                            }),
+                        )
                     }),
                 )?;
             }
-            Event::InlayHints {
+            Event::TreeExpand { tree, node_id } => {
+                self.expand_tree_node(tree, node_id)?;
+            }
+            Event::Selected { id, index } => {
+                self.choose(id, index)?;
+            }
+            Event::Previewed { id, accepted } => {
+                let choice = self.choices.borrow_mut().pending.remove(&id);
+                if let Some(Choice::WorkspaceEdit { changes, then }) = choice {
+                    self.apply_previewed_edit(changes, accepted, then)?;
+                }
+            }
+            Event::OpenLink {
                 lang_id,
                 text_document,
+                position,
             } => {
-                let handler = self.handler_for(&lang_id).ok_or(LspcError::NotStarted)?;
-                let text_document_clone = text_document.clone();
-                let params = InlayHintsParams { text_document };
-                handler.lsp_request::<InlayHints>(
+                self.sync_document(&text_document)?;
+                let handler = self.handler_for(&lang_id, &text_document, "document link", |c| {
+                    c.document_link_provider.is_some()
+                })?;
+                let params = DocumentLinkParams {
+                    text_document: text_document.clone(),
+                };
+                handler.lsp_request::<DocumentLinks>(
                     params,
-                    Box::new(move |editor: &mut E, _handler, response| {
-                        editor.inline_hints(&text_document_clone, &response)?;
+                    Box::new(move |editor: &mut E, handler, response| {
+                        let cursor = Range::new(position, position);
+                        let link = response
+                            .unwrap_or_default()
+                            .into_iter()
+                            .find(|link| range_contains(&link.range, &cursor));
+                        let link = match link {
+                            Some(link) => link,
+                            None => {
+                                editor.message("No link under cursor")?;
+                                return Ok(());
+                            }
+                        };
+                        if let Some(ref target) = link.target {
+                            editor.open_link(target)?;
+                            return Ok(());
+                        }
+                        if !handler.document_link_resolve_provider() {
+                            return Ok(());
+                        }
+
+                        handler.lsp_request::<DocumentLinkResolve>(
+                            link,
+                            Box::new(|editor: &mut E, _handler, resolved| {
+                                match resolved.target {
+                                    Some(ref target) => editor.open_link(target)?,
+                                    None => editor.message("Link has no target")?,
+                                }
+
+                                Ok(())
+                            }),
+                        )?;
 
                         Ok(())
                     }),
                 )?;
             }
-            Event::FormatDoc {
+            Event::ColorPresentation {
                 lang_id,
-                text_document_lines,
                 text_document,
+                position,
             } => {
-                let handler = self.handler_for(&lang_id).ok_or(LspcError::NotStarted)?;
-                let options = FormattingOptions {
-                    tab_size: handler.lang_settings.indentation,
-                    insert_spaces: handler.lang_settings.indentation_with_space,
-                    properties: HashMap::new(),
+                let choices = Rc::clone(&self.choices);
+                let handler = self.handler_for(
+                    &lang_id,
+                    &text_document,
+                    "document color",
+                    supports_document_color,
+                )?;
+                let cursor = Range::new(position, position);
+                let color = handler
+                    .document_colors
+                    .get(&text_document.uri)
+                    .and_then(|colors| {
+                        colors
+                            .iter()
+                            .find(|color| range_contains(&color.range, &cursor))
+                    })
+                    .cloned();
+                let color = match color {
+                    Some(color) => color,
+                    None => {
+                        self.editor.message("No color under cursor")?;
+                        return Ok(());
+                    }
                 };
-                let params = DocumentFormattingParams {
+
+                let uri = text_document.uri.clone();
+                let range = color.range;
+                let params = ColorPresentationParams {
                     text_document,
-                    options,
+                    color: color.color,
+                    range,
                 };
-                handler.lsp_request::<Formatting>(
+                handler.lsp_request::<ColorPresentationRequest>(
                     params,
-                    Box::new(move |editor: &mut E, _handler, response| {
-                        if let Some(edits) = response {
-                            editor.apply_edits(&text_document_lines, &edits)?;
-                        }
+                    Box::new(move |editor: &mut E, _handler, presentations| {
+                        let labels = presentations
+                            .iter()
+                            .map(|presentation| presentation.label.clone())
+                            .collect::<Vec<_>>();
+                        let choice = Choice::ColorPresentation {
+                            uri,
+                            range,
+                            presentations,
+                        };
+                        ask_choice(editor, &choices, "Color presentations:", &labels, choice)?;
 
                         Ok(())
                     }),
                 )?;
             }
-            Event::DidOpen {
-                buf_id,
+            Event::SemanticTokens {
+                lang_id,
                 text_document,
+                range,
             } => {
-                let file_path = text_document.uri.path();
-                let handler = handler_of(&mut self.lsp_handlers, &file_path).ok_or_else(|| {
-                    log::info!("Unmanaged file: {:?}", text_document.uri);
-                    MainLoopError::IgnoredMessage
-                })?;
+                self.sync_document(&text_document)?;
+                let handler =
+                    match self.semantic_tokens_handler(&lang_id, &text_document, |options| {
+                        options.supports_range()
+                    }) {
+                        // Requested on every scroll, don't report it
+                        Err(_) => return Ok(()),
+                        Ok(handler) => handler,
+                    };
+                if handler.semantic_tokens.contains_key(&text_document.uri) {
+                    return Ok(());
+                }
 
-                self.editor.watch_file_events(&text_document)?;
-                self.tracking_buffers.insert(
-                    buf_id,
-                    TrackingBuffer::new(handler.lang_id.clone(), text_document.clone()),
-                );
+                let params = SemanticTokensRangeParams {
+                    text_document: text_document.clone(),
+                    range,
+                };
+                handler.lsp_request::<SemanticTokensRange>(
+                    params,
+                    Box::new(move |editor: &mut E, handler, response| {
+                        // Tokens of the whole document came first, they are newer
+                        if handler.semantic_tokens.contains_key(&text_document.uri) {
+                            return Ok(());
+                        }
+                        let tokens = response.unwrap_or_default();
+                        if let Some(options) = handler.semantic_tokens_options() {
+                            editor.show_semantic_tokens(
+                                &text_document,
+                                Some(&range),
+                                &options.legend.decode(&tokens.data),
+                            )?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
             }
-            Event::DidChange {
-                buf_id,
-                version,
-                content_change,
+            Event::ShrinkSelection {
+                text_document,
+                range,
             } => {
-                let (handler, tracking_buf) =
-                    self.handler_for_buffer(&buf_id).ok_or_else(|| {
-                        log::info!(
-                            "Received changed event for nontracking buffer: {:?}",
-                            buf_id
-                        );
-                        MainLoopError::IgnoredMessage
-                    })?;
-
-                if !tracking_buf.sent_did_open {
-                    handler.lsp_notify::<noti::DidOpenTextDocument>(
-                        lsp::DidOpenTextDocumentParams {
-                            text_document: lsp::TextDocumentItem {
-                                uri: tracking_buf.text_document.uri.clone(),
-                                language_id: tracking_buf.lang_id.clone(),
-                                version,
-                                text: content_change.text,
-                            },
-                        },
-                    )?;
-                    tracking_buf.sent_did_open = true;
-                } else {
-                    tracking_buf.delay_sync_in(
-                        Duration::from_millis(SYNC_DELAY_MS),
-                        version,
-                        content_change,
-                    );
+                let mut selection_chains = self.selection_chains.borrow_mut();
+                let chain = match selection_chains.get_mut(&text_document.uri) {
+                    Some(chain) if chain.ranges.get(chain.selected) == Some(&range) => chain,
+                    _ => return Ok(()),
+                };
+                if chain.selected > 0 {
+                    chain.selected -= 1;
                 }
-            }
-            Event::DidClose { buf_id } => {
-                let (handler, tracking_buf) =
-                    self.handler_for_buffer(&buf_id).ok_or_else(|| {
-                        log::info!(
-                            "Received changed event for nontracking buffer: {:?}",
-                            buf_id
-                        );
-                        MainLoopError::IgnoredMessage
-                    })?;
-
-                tracking_buf.sync_pending_changes(handler)?;
-                handler.lsp_notify::<noti::DidCloseTextDocument>(
-                    lsp::DidCloseTextDocumentParams {
-                        text_document: tracking_buf.text_document.clone(),
-                    },
-                )?;
+                self.editor
+                    .set_selection(&text_document, &chain.ranges[chain.selected])?;
             }
         }
 
@@ -597,7 +3279,138 @@ impl<E: Editor> Lspc<E> {
     fn handle_lsp_msg(&mut self, index: usize, msg: LspMessage) -> Result<(), LspcError> {
         let lsp_handler = &mut self.lsp_handlers[index];
         match msg {
-            LspMessage::Request(_req) => {}
+            LspMessage::Request(mut req) => {
+                req = match req.cast::<RegisterCapability>() {
+                    Ok((id, params)) => {
+                        lsp_handler.register_capabilities(params.registrations);
+                        lsp_handler.lsp_respond::<RegisterCapability>(id, ())?;
+
+                        return Ok(());
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<WorkspaceConfiguration>() {
+                    Ok((id, params)) => {
+                        let result = params
+                            .items
+                            .iter()
+                            .map(|item| {
+                                lsp_handler.configuration(item.section.as_ref().map(String::as_str))
+                            })
+                            .collect::<Vec<_>>();
+                        lsp_handler.lsp_respond::<WorkspaceConfiguration>(id, result)?;
+
+                        return Ok(());
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<WorkspaceFoldersRequest>() {
+                    Ok((id, ())) => {
+                        let folders = lsp_handler.workspace_folders().to_vec();
+                        lsp_handler.lsp_respond::<WorkspaceFoldersRequest>(id, Some(folders))?;
+
+                        return Ok(());
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<ShowMessageRequest>() {
+                    Ok((id, params)) => {
+                        let actions = params.actions.unwrap_or_default();
+                        if actions.is_empty() {
+                            self.editor.show_message(&ShowMessageParams {
+                                typ: params.typ,
+                                message: params.message,
+                            })?;
+                            lsp_handler.lsp_respond::<ShowMessageRequest>(id, None)?;
+
+                            return Ok(());
+                        }
+
+                        // Answered once the user has chosen
+                        let titles = actions
+                            .iter()
+                            .map(|action| action.title.clone())
+                            .collect::<Vec<_>>();
+                        let prompt = format!("[{}] {}", lsp_handler.name, params.message);
+                        let choice = Choice::MessageAction {
+                            server: lsp_handler.key(),
+                            request_id: id,
+                            actions,
+                        };
+                        let asked =
+                            ask_choice(&mut self.editor, &self.choices, &prompt, &titles, choice);
+                        if asked.is_err() {
+                            lsp_handler.lsp_respond::<ShowMessageRequest>(id, None)?;
+                        }
+
+                        return Ok(asked?);
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<ApplyAnnotatedWorkspaceEdit>() {
+                    Ok((id, params)) => {
+                        let title = params
+                            .label
+                            .unwrap_or_else(|| format!("Edit from {}", lsp_handler.name));
+                        let preview = lsp_handler.lang_settings.preview_edits;
+                        let server = lsp_handler.key();
+                        let reply = |server| AfterEdit::Reply {
+                            server,
+                            request_id: id,
+                        };
+                        // Answered once the preview is, if there is one
+                        let result = AnnotatedWorkspaceEdit::from_value(params.edit)
+                            .map_err(|e| EditorError::Failed(format!("{}", e)))
+                            .and_then(|edit| {
+                                confirm_workspace_edit(
+                                    &mut self.editor,
+                                    &self.choices,
+                                    &title,
+                                    &edit,
+                                    preview,
+                                    reply(server.clone()),
+                                )
+                            });
+
+                        return match result {
+                            Ok(Some(then)) => self.after_edit(then, Ok(true)),
+                            Ok(None) => Ok(()),
+                            Err(e) => self.after_edit(reply(server), Err(e)),
+                        };
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<InlayHintRefresh>() {
+                    Ok((id, ())) => {
+                        lsp_handler.lsp_respond::<InlayHintRefresh>(id, ())?;
+
+                        return self.refresh_inlay_hints(index);
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<WorkDoneProgressCreate>() {
+                    Ok((id, params)) => {
+                        lsp_handler
+                            .progress
+                            .insert(progress_token(&params.token), String::new());
+                        lsp_handler.lsp_respond::<WorkDoneProgressCreate>(id, ())?;
+
+                        return Ok(());
+                    }
+                    Err(req) => req,
+                };
+                req = match req.cast::<UnregisterCapability>() {
+                    Ok((id, params)) => {
+                        lsp_handler.unregister_capabilities(params.unregisterations);
+                        lsp_handler.lsp_respond::<UnregisterCapability>(id, ())?;
+
+                        return Ok(());
+                    }
+                    Err(req) => req,
+                };
+
+                log::warn!("Not supported request: {:?}", req);
+            }
             LspMessage::Notification(mut noti) => {
                 noti = match noti.cast::<noti::ShowMessage>() {
                     Ok(params) => {
@@ -607,6 +3420,73 @@ impl<E: Editor> Lspc<E> {
                     }
                     Err(noti) => noti,
                 };
+                noti = match noti.cast::<noti::LogMessage>() {
+                    Ok(params) => {
+                        if LogLevel::from(params.typ) <= lsp_handler.lang_settings.log_level {
+                            self.editor.log_message(&lsp_handler.name, &params)?;
+                        }
+
+                        return Ok(());
+                    }
+                    Err(noti) => noti,
+                };
+                noti = match noti.cast::<Progress>() {
+                    Ok(params) => {
+                        // Partial results are never requested
+                        let progress = match serde_json::from_value(params.value) {
+                            Ok(progress) => progress,
+                            Err(e) => {
+                                log::warn!("Invalid work done progress: {}", e);
+                                return Ok(());
+                            }
+                        };
+                        let token = progress_token(&params.token);
+                        let title = match progress {
+                            WorkDoneProgress::Begin { ref title, .. } => {
+                                lsp_handler.progress.insert(token.clone(), title.clone());
+                                title.clone()
+                            }
+                            WorkDoneProgress::Report { .. } => lsp_handler
+                                .progress
+                                .get(&token)
+                                .cloned()
+                                .unwrap_or_default(),
+                            WorkDoneProgress::End { .. } => {
+                                lsp_handler.progress.remove(&token).unwrap_or_default()
+                            }
+                        };
+                        self.editor
+                            .show_progress(&lsp_handler.name, &token, &title, &progress)?;
+
+                        return Ok(());
+                    }
+                    Err(noti) => noti,
+                };
+                noti = match noti.cast::<noti::PublishDiagnostics>() {
+                    Ok(params) => {
+                        let server = lsp_handler.key();
+                        let mut diagnostics = params.diagnostics;
+                        for diagnostic in diagnostics.iter_mut() {
+                            if diagnostic.source.is_none() {
+                                diagnostic.source = Some(server.name.clone());
+                            }
+                        }
+
+                        let by_server = self
+                            .diagnostics
+                            .entry(params.uri.clone())
+                            .or_insert_with(HashMap::new);
+                        by_server.insert(server, diagnostics);
+
+                        let mut merged = by_server.values().flatten().cloned().collect::<Vec<_>>();
+                        merged.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+                        self.editor
+                            .show_diagnostics(&TextDocumentIdentifier::new(params.uri), &merged)?;
+
+                        return Ok(());
+                    }
+                    Err(noti) => noti,
+                };
 
                 log::warn!("Not supported notification: {:?}", noti);
             }
@@ -640,15 +3520,47 @@ impl<E: Editor> Lspc<E> {
 
         for buf_id in sync_due_buffers {
             log::debug!("Buffer changes due: {:?}", buf_id);
-            let (handler, tracking_buf) = self.handler_for_buffer(&buf_id).ok_or_else(|| {
-                log::info!(
-                    "Received changed event for nontracking buffer: {:?}",
-                    buf_id
-                );
-                MainLoopError::IgnoredMessage
-            })?;
-            tracking_buf.sync_pending_changes(handler)?;
+            let (mut handlers, tracking_buf) =
+                self.handlers_for_buffer(&buf_id).ok_or_else(|| {
+                    log::info!(
+                        "Received changed event for nontracking buffer: {:?}",
+                        buf_id
+                    );
+                    MainLoopError::IgnoredMessage
+                })?;
+            tracking_buf.sync_pending_changes(&mut handlers)?;
+
+            let lang_id = tracking_buf.lang_id.clone();
+            let text_document = tracking_buf.text_document.clone();
+            self.refresh_document(&lang_id, &text_document)?;
+        }
+
+        for handler in self.lsp_handlers.iter_mut() {
+            handler.notify_watched_files()?;
+        }
+        self.expire_pending_replies(now)?;
+
+        Ok(())
+    }
+
+    fn execute_pending_commands(&mut self) -> Result<(), LspcError> {
+        let commands = std::mem::replace(&mut *self.pending_commands.borrow_mut(), Vec::new());
+        for (server, command) in commands {
+            let handler = self
+                .lsp_handlers
+                .iter_mut()
+                .find(|handler| handler.key() == server)
+                .ok_or(LspcError::NotStarted)?;
+            let params = ExecuteCommandParams {
+                command: command.command,
+                arguments: command.arguments.unwrap_or_default(),
+            };
+            handler.lsp_request::<ExecuteCommand>(
+                params,
+                Box::new(|_editor: &mut E, _handler, _response| Ok(())),
+            )?;
         }
+
         Ok(())
     }
 }
@@ -659,6 +3571,13 @@ impl<E: Editor> Lspc<E> {
             editor,
             lsp_handlers: Vec::new(),
             tracking_buffers: HashMap::new(),
+            diagnostics: HashMap::new(),
+            pending_commands: Rc::new(RefCell::new(Vec::new())),
+            pending_replies: Rc::new(RefCell::new(Vec::new())),
+            document_highlight: None,
+            selection_chains: Rc::new(RefCell::new(HashMap::new())),
+            trees: Rc::new(RefCell::new(TreeNodes::default())),
+            choices: Rc::new(RefCell::new(Choices::default())),
         }
     }
 
@@ -673,9 +3592,135 @@ impl<E: Editor> Lspc<E> {
                 SelectedMsg::Lsp(index, msg) => self.handle_lsp_msg(index, msg),
                 SelectedMsg::TimerTick => self.handle_timer_tick(),
             };
-            if let Err(e) = result {
-                log::error!("Handle error: {:?}", e);
+            match result {
+                Err(LspcError::NotSupported(feature)) => {
+                    let msg = format!("server does not support {}", feature);
+                    if let Err(e) = self.editor.message(&msg) {
+                        log::error!("Cannot report unsupported feature: {:?}", e);
+                    }
+                }
+                Err(e) => log::error!("Handle error: {:?}", e),
+                Ok(()) => {}
             }
+
+            if let Err(e) = self.execute_pending_commands() {
+                log::error!("Execute command error: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_action_item(server: &str, title: &str) -> CodeActionItem {
+        CodeActionItem {
+            server: ServerKey {
+                name: server.to_owned(),
+                root_path: "/project".to_owned(),
+            },
+            action: CodeActionOrCommand::Command(lsp::Command {
+                title: title.to_owned(),
+                command: title.to_owned(),
+                arguments: None,
+            }),
+            edit: None,
+            preview: false,
         }
     }
+
+    #[test]
+    fn test_code_action_merge_with_failed_server() {
+        let mut merge = CodeActionMerge {
+            remaining: 2,
+            actions: Vec::new(),
+        };
+
+        let failed = Err(LangServerError::InvalidResponse("error".to_owned()));
+        assert!(merge.add(failed).is_none());
+
+        let actions = merge
+            .add(Ok(vec![code_action_item("rls", "fix")]))
+            .expect("all servers answered");
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].server.name, "rls");
+    }
+
+    #[test]
+    fn test_tracking_buffer_line_count() {
+        let uri = Url::parse("file:///project/src/main.rs").unwrap();
+        let mut tracking_buf =
+            TrackingBuffer::new("rust".to_owned(), TextDocumentIdentifier::new(uri));
+        let lines = |start, end| Some(Range::new(Position::new(start, 0), Position::new(end, 0)));
+
+        tracking_buf.update_line_count(None, 10);
+        assert_eq!(tracking_buf.line_count, 10);
+        // Two lines deleted
+        tracking_buf.update_line_count(lines(2, 4), 0);
+        assert_eq!(tracking_buf.line_count, 8);
+        // One line replaced by three
+        tracking_buf.update_line_count(lines(0, 1), 3);
+        assert_eq!(tracking_buf.line_count, 10);
+        // Lines appended at the end
+        tracking_buf.update_line_count(lines(10, 10), 2);
+        assert_eq!(tracking_buf.line_count, 12);
+    }
+
+    #[test]
+    fn test_tree_nodes_cleared_by_tree() {
+        let trees: Trees = Rc::new(RefCell::new(TreeNodes::default()));
+        let uri = Url::parse("file:///project/src/main.rs").unwrap();
+        let node = || TreeNode::Calls {
+            server: code_action_item("rls", "").server,
+            item: CallHierarchyItem {
+                name: "main".to_owned(),
+                kind: lsp::SymbolKind::Function,
+                detail: None,
+                uri: uri.clone(),
+                range: Range::default(),
+                selection_range: Range::default(),
+                data: None,
+            },
+            incoming: true,
+        };
+        let location = Location::new(uri.clone(), Range::default());
+
+        let incoming = tree_item(&trees, "incoming", "a".to_owned(), location.clone(), node());
+        let outgoing = tree_item(&trees, "outgoing", "b".to_owned(), location, node());
+        assert!(trees.borrow().get("outgoing", incoming.id).is_none());
+
+        trees.borrow_mut().clear("incoming");
+        assert!(trees.borrow().get("incoming", incoming.id).is_none());
+        assert!(trees.borrow().get("outgoing", outgoing.id).is_some());
+    }
+
+    #[test]
+    fn test_accepted_hunks_of_previewed_changes() {
+        let uri = Url::parse("file:///project/src/main.rs").unwrap();
+        let text_edit = |line| {
+            TextEdit::new(
+                Range::new(Position::new(line, 0), Position::new(line, 1)),
+                "a".to_owned(),
+            )
+        };
+        let mut changes = HashMap::new();
+        changes.insert(
+            uri.clone(),
+            vec![text_edit(0), text_edit(40), text_edit(80)],
+        );
+
+        let changes = preview_changes(&WorkspaceEdit::new(changes));
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().all(|(index, _)| *index == 0));
+
+        // The second hunk is skipped
+        assert_eq!(
+            accepted_changes(changes, &[0, 2]),
+            vec![WorkspaceChange::Edits(
+                uri,
+                vec![text_edit(0), text_edit(80)]
+            )]
+        );
+    }
 }
@@ -0,0 +1,221 @@
+// Unified diff of lines, to preview edits before they are applied
+use std::cmp;
+
+use lsp_types::TextEdit;
+
+// Unchanged lines around changes of previewed edits
+pub const PREVIEW_CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+// Past this many cells, changed lines are shown as all replaced rather
+// than looking for their longest common subsequence
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+// How each line of `old` and `new` goes into the diff, in order
+fn diff_ops(old: &[String], new: &[String]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut ops = vec![Op::Equal; prefix];
+    ops.extend(changed_ops(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    ));
+    ops.extend(vec![Op::Equal; suffix]);
+    ops
+}
+
+fn changed_ops(old: &[String], new: &[String]) -> Vec<Op> {
+    let (n, m) = (old.len(), new.len());
+    let mut ops = Vec::with_capacity(n + m);
+    if n * m > MAX_LCS_CELLS {
+        ops.extend(vec![Op::Delete; n]);
+        ops.extend(vec![Op::Insert; m]);
+        return ops;
+    }
+
+    // `lengths[i][j]` is the length of the longest common subsequence
+    // of `old[i..]` and `new[j..]`
+    let mut lengths = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lengths[i + 1][j], lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            ops.push(Op::Equal);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            ops.push(Op::Delete);
+            i += 1;
+        } else {
+            ops.push(Op::Insert);
+            j += 1;
+        }
+    }
+    ops.extend(vec![Op::Delete; n - i]);
+    ops.extend(vec![Op::Insert; m - j]);
+    ops
+}
+
+// `start,count` of a hunk header, empty ranges start at the line
+// before them
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// Hunks changing `old` into `new`, with `context` unchanged lines
+// around changes, without file headers
+pub fn unified_diff(old: &[String], new: &[String], context: usize) -> Vec<String> {
+    let ops = diff_ops(old, new);
+
+    // Lines of `old` and `new` at each op
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        positions.push((i, j));
+        match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+
+    // Ops of each hunk, close changes share their hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, _) in ops.iter().enumerate().filter(|(_, op)| **op != Op::Equal) {
+        let start = index.saturating_sub(context);
+        let end = cmp::min(index + 1 + context, ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (start, end) in hunks {
+        let hunk_ops = &ops[start..end];
+        let (old_start, new_start) = positions[start];
+        let old_count = hunk_ops.iter().filter(|op| **op != Op::Insert).count();
+        let new_count = hunk_ops.iter().filter(|op| **op != Op::Delete).count();
+        lines.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        for (op, (i, j)) in hunk_ops.iter().zip(&positions[start..end]) {
+            lines.push(match op {
+                Op::Equal => format!(" {}", old[*i]),
+                Op::Delete => format!("-{}", old[*i]),
+                Op::Insert => format!("+{}", new[*j]),
+            });
+        }
+    }
+
+    lines
+}
+
+// Edits of a document grouped by the hunk showing them, edits closer
+// than twice `context` lines go in the same one. Edits at the same
+// position keep their order
+pub fn edit_hunks(edits: &[TextEdit], context: usize) -> Vec<Vec<TextEdit>> {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+
+    let mut hunks: Vec<(u64, Vec<TextEdit>)> = Vec::new();
+    for edit in edits {
+        match hunks.last_mut() {
+            Some((end, hunk)) if edit.range.start.line <= *end + 2 * context as u64 + 1 => {
+                *end = cmp::max(*end, edit.range.end.line);
+                hunk.push(edit);
+            }
+            _ => hunks.push((edit.range.end.line, vec![edit])),
+        }
+    }
+
+    hunks.into_iter().map(|(_, hunk)| hunk).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = lines("a\nb\nc\nd\ne\nf\ng\nh\ni");
+        let new = lines("a\nB\nc\nd\ne\nf\ng\nh\ni\nj");
+        let expected = vec![
+            "@@ -1,3 +1,3 @@",
+            " a",
+            "-b",
+            "+B",
+            " c",
+            "@@ -9 +9,2 @@",
+            " i",
+            "+j",
+        ];
+
+        assert_eq!(unified_diff(&old, &new, 1), expected);
+        assert_eq!(unified_diff(&old, &new, 3).len(), 12);
+    }
+
+    #[test]
+    fn test_unified_diff_of_empty_text() {
+        let expected = vec!["@@ -0,0 +1,2 @@", "+a", "+b"];
+
+        assert_eq!(unified_diff(&[], &lines("a\nb"), 3), expected);
+        assert!(unified_diff(&lines("a"), &lines("a"), 3).is_empty());
+    }
+
+    #[test]
+    fn test_edit_hunks() {
+        use lsp_types::{Position, Range};
+
+        let edit = |line, text: &str| {
+            TextEdit::new(
+                Range::new(Position::new(line, 0), Position::new(line, 1)),
+                text.to_owned(),
+            )
+        };
+        let edits = vec![edit(20, "c"), edit(0, "a"), edit(3, "b"), edit(0, "d")];
+
+        assert_eq!(
+            edit_hunks(&edits, 1),
+            vec![
+                vec![edit(0, "a"), edit(0, "d"), edit(3, "b")],
+                vec![edit(20, "c")]
+            ]
+        );
+        assert_eq!(edit_hunks(&edits, 0).len(), 3);
+    }
+}
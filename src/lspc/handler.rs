@@ -1,21 +1,33 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
 };
 
 use crossbeam::channel::Receiver;
 use lsp_types::{
-    notification::{Initialized, Notification},
+    notification::{
+        Cancel, DidChangeConfiguration, DidChangeWatchedFiles, DidChangeWorkspaceFolders,
+        Initialized, Notification,
+    },
     request::Request,
-    InitializeResult, ServerCapabilities,
+    CancelParams, CodeLens, ColorInformation, DidChangeConfigurationParams,
+    DidChangeWatchedFilesRegistrationOptions, DidChangeWorkspaceFoldersParams, InitializeResult,
+    NumberOrString, Registration, ServerCapabilities, Unregistration, WorkspaceFolder,
+    WorkspaceFoldersChangeEvent,
 };
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use url::Url;
 
 use super::{
     msg::{LspMessage, RawNotification, RawRequest, RawResponse},
-    Editor, LangServerError, LspcError,
+    types::{SemanticTokens, SemanticTokensOptions},
+    watcher::FileWatcher,
+    Editor, LangServerError, LogLevel, LspcError,
 };
 use crate::rpc;
 
@@ -27,25 +39,69 @@ pub struct Callback<E: Editor> {
     pub func: RawCallback<E>,
 }
 
+// A server is identified by its name and the root it was started in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerKey {
+    pub name: String,
+    pub root_path: String,
+}
+
+pub fn to_workspace_folder(root_path: &str) -> Option<WorkspaceFolder> {
+    let uri = Url::from_file_path(root_path).ok()?;
+    let name = Path::new(root_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(root_path)
+        .to_owned();
+
+    Some(WorkspaceFolder { uri, name })
+}
+
 pub struct LangSettings {
+    // Files found under new roots are added as workspace folders
+    pub root_markers: Vec<String>,
     pub indentation: u64,
     pub indentation_with_space: bool,
+    pub format_on_save: bool,
+    pub preview_edits: bool,
+    pub log_level: LogLevel,
 }
 
 pub struct LangServerHandler<E: Editor> {
     pub lang_id: String,
+    pub name: String,
+    // Name the server gives itself on initialization
+    pub server_name: Option<String>,
     rpc_client: rpc::Client<LspMessage>,
     callbacks: Vec<Callback<E>>,
     next_id: AtomicU64,
     root_path: String,
+    // Roots served by this server, `root_path` is the first one
+    workspace_folders: Vec<WorkspaceFolder>,
     // None if server is not started
     server_capabilities: Option<ServerCapabilities>,
+    // Capabilities as sent by the server, including the ones
+    // `ServerCapabilities` does not know about
+    raw_capabilities: Option<Value>,
+    semantic_tokens_options: Option<SemanticTokensOptions>,
     pub lang_settings: LangSettings,
+    // User settings for the server, `Null` if not configured
+    pub settings: Value,
+    // Latest code lenses of each document
+    pub code_lenses: HashMap<Url, Vec<CodeLens>>,
+    // Latest full semantic tokens of each document, base of delta requests
+    pub semantic_tokens: HashMap<Url, SemanticTokens>,
+    // Latest colors of each document
+    pub document_colors: HashMap<Url, Vec<ColorInformation>>,
+    // Titles of work done progress by token, empty until it begins
+    pub progress: HashMap<String, String>,
+    file_watcher: FileWatcher,
 }
 
 impl<E: Editor> LangServerHandler<E> {
     pub fn new(
         lang_id: String,
+        name: String,
         command: &String,
         lang_settings: LangSettings,
         args: &[String],
@@ -67,18 +123,86 @@ impl<E: Editor> LangServerHandler<E> {
         Ok(LangServerHandler {
             rpc_client,
             lang_id,
+            name,
+            server_name: None,
             next_id: AtomicU64::new(1),
+            file_watcher: FileWatcher::new(&root_path),
+            workspace_folders: to_workspace_folder(&root_path).into_iter().collect(),
             root_path,
             callbacks: Vec::new(),
             server_capabilities: None,
+            raw_capabilities: None,
+            semantic_tokens_options: None,
             lang_settings,
+            settings: Value::Null,
+            code_lenses: HashMap::new(),
+            semantic_tokens: HashMap::new(),
+            document_colors: HashMap::new(),
+            progress: HashMap::new(),
         })
     }
 
-    pub fn include_file(&self, file_path: &str) -> bool {
-        let file_path = Path::new(file_path);
+    pub fn include_file(&self, file_path: &Path) -> bool {
+        self.root_of(file_path).is_some()
+    }
+
+    // The most specific root of this server containing `file_path`
+    pub fn root_of(&self, file_path: &Path) -> Option<PathBuf> {
+        std::iter::once(PathBuf::from(&self.root_path))
+            .chain(
+                self.workspace_folders
+                    .iter()
+                    .filter_map(|folder| folder.uri.to_file_path().ok()),
+            )
+            .filter(|root| file_path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+    }
+
+    pub fn workspace_folders(&self) -> &[WorkspaceFolder] {
+        &self.workspace_folders
+    }
+
+    // Server can be told about new roots instead of starting another one
+    pub fn supports_workspace_folders(&self) -> bool {
+        let folders = match self.raw_capabilities {
+            Some(ref capabilities) => capabilities.pointer("/workspace/workspaceFolders"),
+            None => None,
+        };
+        let supported = folders.and_then(|folders| folders.get("supported"));
+        let change_notifications = folders.and_then(|folders| folders.get("changeNotifications"));
 
-        file_path.starts_with(&self.root_path)
+        match (supported, change_notifications) {
+            (Some(Value::Bool(true)), Some(Value::Bool(true))) => true,
+            (Some(Value::Bool(true)), Some(Value::String(_))) => true,
+            _ => false,
+        }
+    }
+
+    pub fn add_workspace_folder(&mut self, folder: WorkspaceFolder) -> Result<(), LangServerError> {
+        let params = DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent {
+                added: vec![folder.clone()],
+                removed: Vec::new(),
+            },
+        };
+        self.lsp_notify::<DidChangeWorkspaceFolders>(params)?;
+        if let Ok(root_path) = folder.uri.to_file_path() {
+            self.file_watcher.add_root(root_path);
+        }
+        self.workspace_folders.push(folder);
+
+        Ok(())
+    }
+
+    pub fn root_path(&self) -> &str {
+        &self.root_path
+    }
+
+    pub fn key(&self) -> ServerKey {
+        ServerKey {
+            name: self.name.clone(),
+            root_path: self.root_path.clone(),
+        }
     }
 
     fn send_msg(&self, msg: LspMessage) -> Result<(), LangServerError> {
@@ -108,29 +232,218 @@ impl<E: Editor> LangServerHandler<E> {
         }
     }
 
-    pub fn initialize_response(
-        &mut self,
-        response: InitializeResult,
-    ) -> Result<(), LangServerError> {
-        let server_capabilities = response.capabilities;
-        self.server_capabilities = Some(server_capabilities);
+    pub fn initialize_response(&mut self, response: Value) -> Result<(), LangServerError> {
+        let raw_capabilities = response.get("capabilities").cloned();
+        self.server_name = response
+            .pointer("/serverInfo/name")
+            .and_then(|name| name.as_str())
+            .map(String::from);
+        let response: InitializeResult = serde_json::from_value(response)
+            .map_err(|e| LangServerError::InvalidResponse(format!("{}", e)))?;
+        self.server_capabilities = Some(response.capabilities);
+        self.semantic_tokens_options = raw_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.get("semanticTokensProvider"))
+            .and_then(|provider| serde_json::from_value(provider.clone()).ok());
+        self.raw_capabilities = raw_capabilities;
 
         self.initialized()?;
 
         Ok(())
     }
 
+    // Characters which trigger signature help, and the ones which
+    // trigger it again while it is shown
+    pub fn signature_help_characters(&self) -> (Vec<String>, Vec<String>) {
+        let triggers = self
+            .server_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.signature_help_provider.as_ref())
+            .and_then(|provider| provider.trigger_characters.clone())
+            .unwrap_or_default();
+        let retriggers = self
+            .raw_capabilities
+            .as_ref()
+            .and_then(|capabilities| {
+                capabilities.pointer("/signatureHelpProvider/retriggerCharacters")
+            })
+            .and_then(|chars| serde_json::from_value(chars.clone()).ok())
+            .unwrap_or_default();
+
+        (triggers, retriggers)
+    }
+
+    pub fn code_lens_resolve_provider(&self) -> bool {
+        self.server_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.code_lens_provider.as_ref())
+            .and_then(|provider| provider.resolve_provider)
+            == Some(true)
+    }
+
+    pub fn document_link_resolve_provider(&self) -> bool {
+        self.server_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.document_link_provider.as_ref())
+            .and_then(|provider| provider.resolve_provider)
+            == Some(true)
+    }
+
+    // Commands the server can run with `workspace/executeCommand`
+    pub fn execute_commands(&self) -> Vec<String> {
+        self.server_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.execute_command_provider.as_ref())
+            .map(|provider| provider.commands.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn on_type_formatting_characters(&self) -> Vec<String> {
+        let provider = self
+            .server_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.document_on_type_formatting_provider.as_ref());
+        match provider {
+            Some(provider) => {
+                let mut chars = vec![provider.first_trigger_character.clone()];
+                chars.extend(provider.more_trigger_character.iter().flatten().cloned());
+                chars
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub fn semantic_tokens_options(&self) -> Option<&SemanticTokensOptions> {
+        self.semantic_tokens_options.as_ref()
+    }
+
+    pub fn raw_capabilities(&self) -> Option<&Value> {
+        self.raw_capabilities.as_ref()
+    }
+
+    // Check that the server provides `feature` before requesting it
+    pub fn ensure_supports<F>(&self, feature: &'static str, provider: F) -> Result<(), LspcError>
+    where
+        F: FnOnce(&ServerCapabilities) -> bool,
+    {
+        match self.server_capabilities {
+            None => Err(LspcError::NotStarted),
+            Some(ref capabilities) if provider(capabilities) => Ok(()),
+            Some(_) => Err(LspcError::NotSupported(feature)),
+        }
+    }
+
+    // Like `ensure_supports` for providers unknown to `ServerCapabilities`,
+    // `provider` is the capability name like `callHierarchyProvider`
+    pub fn ensure_provides(&self, feature: &'static str, provider: &str) -> Result<(), LspcError> {
+        let capabilities = match self.raw_capabilities {
+            None => return Err(LspcError::NotStarted),
+            Some(ref capabilities) => capabilities,
+        };
+        match capabilities.get(provider) {
+            Some(Value::Bool(true)) | Some(Value::Object(_)) => Ok(()),
+            _ => Err(LspcError::NotSupported(feature)),
+        }
+    }
+
+    // Settings under a dotted `section` like `rust-analyzer.cargo`
+    pub fn configuration(&self, section: Option<&str>) -> Value {
+        let section = match section {
+            Some(section) => section,
+            None => return self.settings.clone(),
+        };
+
+        section
+            .split('.')
+            .try_fold(&self.settings, |settings, key| settings.get(key))
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
+    pub fn update_settings(&mut self, settings: Value) -> Result<(), LangServerError> {
+        self.settings = settings;
+        self.notify_configuration()
+    }
+
+    pub fn notify_configuration(&mut self) -> Result<(), LangServerError> {
+        let params = DidChangeConfigurationParams {
+            settings: self.settings.clone(),
+        };
+        self.lsp_notify::<DidChangeConfiguration>(params)
+    }
+
     pub fn initialized(&mut self) -> Result<(), LangServerError> {
         log::debug!("Sending initialized notification");
 
         self.lsp_notify::<Initialized>(lsp_types::InitializedParams {})
     }
 
+    pub fn register_capabilities(&mut self, registrations: Vec<Registration>) {
+        for registration in registrations {
+            if registration.method != DidChangeWatchedFiles::METHOD {
+                log::info!("Not supported registration: {}", registration.method);
+                continue;
+            }
+
+            let options = registration
+                .register_options
+                .map(serde_json::from_value::<DidChangeWatchedFilesRegistrationOptions>);
+            match options {
+                Some(Ok(options)) => {
+                    self.file_watcher
+                        .register(registration.id, options.watchers);
+                }
+                _ => log::warn!("Invalid watched files registration: {}", registration.id),
+            }
+        }
+    }
+
+    pub fn unregister_capabilities(&mut self, unregistrations: Vec<Unregistration>) {
+        for unregistration in unregistrations {
+            if unregistration.method == DidChangeWatchedFiles::METHOD {
+                self.file_watcher.unregister(&unregistration.id);
+            }
+        }
+    }
+
+    // Send pending file changes if they are due
+    pub fn notify_watched_files(&mut self) -> Result<(), LangServerError> {
+        if let Some(params) = self.file_watcher.poll(Instant::now()) {
+            self.lsp_notify::<DidChangeWatchedFiles>(params)?;
+        }
+
+        Ok(())
+    }
+
     pub fn lsp_request<R: Request>(
         &mut self,
         params: R::Params,
         cb: Box<dyn FnOnce(&mut E, &mut LangServerHandler<E>, R::Result) -> Result<(), LspcError>>,
-    ) -> Result<(), LangServerError>
+    ) -> Result<u64, LangServerError>
+    where
+        R::Params: Serialize + Debug,
+        R::Result: DeserializeOwned + 'static,
+        E: 'static,
+    {
+        self.lsp_request_result::<R>(
+            params,
+            Box::new(move |e, handler, response| cb(e, handler, response?)),
+        )
+    }
+
+    // Like `lsp_request`, `cb` is also called when the server answers
+    // with an error or an invalid result
+    pub fn lsp_request_result<R: Request>(
+        &mut self,
+        params: R::Params,
+        cb: Box<
+            dyn FnOnce(
+                &mut E,
+                &mut LangServerHandler<E>,
+                Result<R::Result, LangServerError>,
+            ) -> Result<(), LspcError>,
+        >,
+    ) -> Result<u64, LangServerError>
     where
         R::Params: Serialize + Debug,
         R::Result: DeserializeOwned + 'static,
@@ -143,18 +456,49 @@ impl<E: Editor> LangServerHandler<E> {
         let raw_callback: RawCallback<E> =
             Box::new(move |e, handler, raw_response: RawResponse| {
                 log::debug!("{} callback", R::METHOD);
-                let response = raw_response.cast::<R>()?;
+                let response = raw_response.cast::<R>().map_err(LangServerError::from);
                 cb(e, handler, response)
             });
         let func = Box::new(raw_callback);
         self.callbacks.push(Callback { id, func });
-        self.request(request)
+        self.request(request)?;
+
+        Ok(id)
+    }
+
+    // Request `id` is not answered yet
+    pub fn is_pending(&self, id: u64) -> bool {
+        self.callbacks.iter().any(|cb| cb.id == id)
+    }
+
+    // Drop the callback of request `id` and tell the server to cancel it,
+    // nothing to do if it is already answered
+    pub fn cancel_request(&mut self, id: u64) -> Result<(), LangServerError> {
+        if self.callback_for(id).is_none() {
+            return Ok(());
+        }
+
+        self.lsp_notify::<Cancel>(CancelParams {
+            id: NumberOrString::Number(id as _),
+        })
     }
 
     fn request(&mut self, request: RawRequest) -> Result<(), LangServerError> {
         self.send_msg(LspMessage::Request(request))
     }
 
+    pub fn lsp_respond<R: Request>(
+        &mut self,
+        id: u64,
+        result: R::Result,
+    ) -> Result<(), LangServerError>
+    where
+        R::Result: Serialize,
+    {
+        let response = RawResponse::ok::<R>(id, &result);
+        self.send_msg(LspMessage::Response(response))
+    }
+
     pub fn lsp_notify<R: Notification>(&mut self, params: R::Params) -> Result<(), LangServerError>
     where
         R::Params: Serialize + Debug,
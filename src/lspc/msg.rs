@@ -44,6 +44,7 @@ impl From<RawResponse> for LspMessage {
 pub struct RawRequest {
     pub id: u64,
     pub method: String,
+    #[serde(default)]
     pub params: Value,
 }
 
@@ -86,6 +87,7 @@ pub enum ErrorCode {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RawNotification {
     pub method: String,
+    #[serde(default)]
     pub params: Value,
 }
 impl Message for LspMessage {
@@ -178,12 +180,16 @@ impl RawResponse {
         R: Request,
         R::Result: serde::de::DeserializeOwned,
     {
-        if let Some(result) = self.result {
-            let result: R::Result = from_value(result).unwrap();
-            return Ok(result);
+        if self.error.is_some() {
+            return Err(self);
         }
 
-        Err(self)
+        // `null` result is deserialized as `None`
+        let result = self.result.clone().unwrap_or(Value::Null);
+        match from_value(result) {
+            Ok(result) => Ok(result),
+            Err(_) => Err(self),
+        }
     }
 }
 
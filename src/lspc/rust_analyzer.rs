@@ -0,0 +1,674 @@
+// Extensions of rust-analyzer, only sent to servers which are known
+// to be it
+use std::{collections::HashMap, path::Path, rc::Rc};
+
+use lsp_types::{
+    self as lsp, request::Request, GotoDefinitionResponse, Location, Position, Range,
+    TextDocumentIdentifier, TextDocumentPositionParams, TextEdit, WorkspaceEdit,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lspc::{
+    ask_choice, confirm_workspace_edit,
+    handler::LangServerHandler,
+    types::{AnnotatedWorkspaceEdit, InlayHint},
+    AfterEdit, Choice, Editor, EditorError, Lspc, LspcError,
+};
+
+// Hints of rust-analyzer before `textDocument/inlayHint`
+pub enum InlayHints {}
+
+impl Request for InlayHints {
+    type Params = InlayHintsParams;
+    type Result = Vec<InlayHint>;
+    const METHOD: &'static str = "rust-analyzer/inlayHints";
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintsParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+pub enum ExpandMacro {}
+
+impl Request for ExpandMacro {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<ExpandedMacro>;
+    const METHOD: &'static str = "rust-analyzer/expandMacro";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExpandedMacro {
+    pub name: String,
+    pub expansion: String,
+}
+
+pub enum ParentModule {}
+
+impl Request for ParentModule {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<GotoDefinitionResponse>;
+    const METHOD: &'static str = "experimental/parentModule";
+}
+
+pub enum JoinLines {}
+
+impl Request for JoinLines {
+    type Params = JoinLinesParams;
+    type Result = Vec<TextEdit>;
+    const METHOD: &'static str = "experimental/joinLines";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinLinesParams {
+    pub text_document: TextDocumentIdentifier,
+    pub ranges: Vec<Range>,
+}
+
+pub enum MatchingBrace {}
+
+impl Request for MatchingBrace {
+    type Params = MatchingBraceParams;
+    type Result = Vec<Position>;
+    const METHOD: &'static str = "experimental/matchingBrace";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchingBraceParams {
+    pub text_document: TextDocumentIdentifier,
+    pub positions: Vec<Position>,
+}
+
+pub enum SyntaxTree {}
+
+impl Request for SyntaxTree {
+    type Params = SyntaxTreeParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/syntaxTree";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeParams {
+    pub text_document: TextDocumentIdentifier,
+    // Whole document if None
+    pub range: Option<Range>,
+}
+
+pub enum AnalyzerStatus {}
+
+impl Request for AnalyzerStatus {
+    type Params = AnalyzerStatusParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/analyzerStatus";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerStatusParams {
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+pub enum Ssr {}
+
+impl Request for Ssr {
+    type Params = SsrParams;
+    // Raw, to keep change annotations
+    type Result = Value;
+    const METHOD: &'static str = "experimental/ssr";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsrParams {
+    // Like `foo($a) ==>> bar($a)`
+    pub query: String,
+    pub parse_only: bool,
+    // Paths of the query are resolved at `position`
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+    // Whole workspace if empty
+    pub selections: Vec<Range>,
+}
+
+// Where structural search and replace is done
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SsrScope {
+    File,
+    Selection(Range),
+    Workspace,
+}
+
+pub enum Runnables {}
+
+impl Request for Runnables {
+    type Params = RunnablesParams;
+    type Result = Vec<Runnable>;
+    const METHOD: &'static str = "experimental/runnables";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnablesParams {
+    pub text_document: TextDocumentIdentifier,
+    // Runnables of the whole document if None
+    pub position: Option<Position>,
+}
+
+// A test, bench or binary, also argument of `rust-analyzer.runSingle`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Runnable {
+    pub label: String,
+    #[serde(flatten)]
+    pub command: RunnableCommand,
+}
+
+// Cargo arguments, or the process to run for older versions
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RunnableCommand {
+    Cargo {
+        args: CargoArgs,
+    },
+    Process {
+        bin: String,
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        cwd: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CargoArgs {
+    pub workspace_root: Option<String>,
+    pub cargo_args: Vec<String>,
+    #[serde(default)]
+    pub cargo_extra_args: Vec<String>,
+    #[serde(default)]
+    pub executable_args: Vec<String>,
+    pub override_cargo: Option<String>,
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+}
+
+const JSON_MESSAGES: &str = "--message-format=json";
+
+impl Runnable {
+    // Cargo prints JSON messages if `json_messages`
+    pub fn command_line(&self, json_messages: bool) -> Vec<String> {
+        match self.command {
+            RunnableCommand::Cargo { ref args } => {
+                let cargo = args.override_cargo.as_ref().map_or("cargo", String::as_str);
+                let mut command = vec![cargo.to_owned()];
+                command.extend(args.cargo_args.iter().cloned());
+                command.extend(args.cargo_extra_args.iter().cloned());
+                if json_messages {
+                    command.push(JSON_MESSAGES.to_owned());
+                }
+                if !args.executable_args.is_empty() {
+                    command.push("--".to_owned());
+                    command.extend(args.executable_args.iter().cloned());
+                }
+                command
+            }
+            RunnableCommand::Process {
+                ref bin, ref args, ..
+            } => {
+                let mut command = vec![bin.clone()];
+                command.extend(args.iter().cloned());
+                if json_messages && bin == "cargo" {
+                    // Before arguments of the executable
+                    let index = command
+                        .iter()
+                        .position(|arg| arg == "--")
+                        .unwrap_or_else(|| command.len());
+                    command.insert(index, JSON_MESSAGES.to_owned());
+                }
+                command
+            }
+        }
+    }
+
+    pub fn cwd(&self) -> Option<&str> {
+        match self.command {
+            RunnableCommand::Cargo { ref args } => args.workspace_root.as_ref(),
+            RunnableCommand::Process { ref cwd, .. } => cwd.as_ref(),
+        }
+        .map(String::as_str)
+    }
+
+    pub fn env(&self) -> &HashMap<String, String> {
+        match self.command {
+            RunnableCommand::Cargo { ref args } => &args.environment,
+            RunnableCommand::Process { ref env, .. } => env,
+        }
+    }
+}
+
+// Run in a terminal, or in `background` with errors and failures
+// sent to the quickfix list
+pub fn run_runnable<E: Editor>(
+    editor: &mut E,
+    runnable: &Runnable,
+    background: bool,
+) -> Result<(), EditorError> {
+    if background {
+        editor.run_in_quickfix(
+            &runnable.label,
+            &runnable.command_line(true),
+            runnable.cwd(),
+            runnable.env(),
+        )
+    } else {
+        editor.run_in_terminal(
+            &runnable.label,
+            &runnable.command_line(false),
+            runnable.cwd(),
+            runnable.env(),
+        )
+    }
+}
+
+// Extension requests the editor can send for a selection
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Extension {
+    ExpandMacro,
+    ParentModule,
+    JoinLines,
+    MatchingBrace,
+    SyntaxTree,
+    AnalyzerStatus,
+}
+
+impl Extension {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "expand_macro" => Some(Extension::ExpandMacro),
+            "parent_module" => Some(Extension::ParentModule),
+            "join_lines" => Some(Extension::JoinLines),
+            "matching_brace" => Some(Extension::MatchingBrace),
+            "syntax_tree" => Some(Extension::SyntaxTree),
+            "analyzer_status" => Some(Extension::AnalyzerStatus),
+            _ => None,
+        }
+    }
+
+    fn feature(self) -> &'static str {
+        match self {
+            Extension::ExpandMacro => "expand macro",
+            Extension::ParentModule => "parent module",
+            Extension::JoinLines => "join lines",
+            Extension::MatchingBrace => "matching brace",
+            Extension::SyntaxTree => "syntax tree",
+            Extension::AnalyzerStatus => "analyzer status",
+        }
+    }
+}
+
+pub fn is_rust_analyzer<E: Editor>(handler: &LangServerHandler<E>) -> bool {
+    // Older versions don't tell their name, their command does
+    let name = handler.server_name.as_ref().unwrap_or(&handler.name);
+    let name = Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(name);
+
+    name.starts_with("rust-analyzer") || name == "ra_lsp_server"
+}
+
+// Run commands which are meant to be handled by the client,
+// false if `command` should be executed by the server
+pub fn run_client_command<E: Editor>(
+    editor: &mut E,
+    command: &lsp::Command,
+) -> Result<bool, LspcError> {
+    let arguments = command.arguments.clone().unwrap_or_default();
+    match command.command.as_str() {
+        "rust-analyzer.runSingle" => {
+            let runnable: Runnable = arguments
+                .into_iter()
+                .next()
+                .and_then(|argument| serde_json::from_value(argument).ok())
+                .ok_or(LspcError::Editor(EditorError::CommandDataInvalid(
+                    "Invalid runnable",
+                )))?;
+            run_runnable(editor, &runnable, false)?;
+        }
+        "rust-analyzer.showReferences" => {
+            // Arguments are uri, position and locations
+            let locations: Vec<Location> = arguments
+                .into_iter()
+                .nth(2)
+                .and_then(|argument| serde_json::from_value(argument).ok())
+                .unwrap_or_default();
+            let items = locations
+                .into_iter()
+                .map(|location| (String::new(), location))
+                .collect::<Vec<_>>();
+            editor.show_symbols("References", &items)?;
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn definition_locations(definition: GotoDefinitionResponse) -> Vec<Location> {
+    match definition {
+        GotoDefinitionResponse::Scalar(location) => vec![location],
+        GotoDefinitionResponse::Array(locations) => locations,
+        GotoDefinitionResponse::Link(links) => links
+            .into_iter()
+            .map(|link| Location::new(link.target_uri, link.target_selection_range))
+            .collect(),
+    }
+}
+
+fn show_text<E: Editor>(editor: &mut E, name: &str, text: &str) -> Result<(), LspcError> {
+    let lines = text.lines().map(String::from).collect::<Vec<_>>();
+    editor.show_scratch(name, &lines, None)?;
+
+    Ok(())
+}
+
+impl<E: Editor> Lspc<E> {
+    // Like `handler_for`, for a feature only rust-analyzer provides
+    pub(super) fn rust_analyzer_handler(
+        &mut self,
+        lang_id: &str,
+        text_document: &TextDocumentIdentifier,
+        feature: &'static str,
+    ) -> Result<&mut LangServerHandler<E>, LspcError> {
+        self.find_handler(lang_id, text_document, |handler| {
+            if handler.raw_capabilities().is_none() {
+                Err(LspcError::NotStarted)
+            } else if !is_rust_analyzer(handler) {
+                Err(LspcError::NotSupported(feature))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    // Let user run one of the runnables at `position`
+    pub(super) fn request_runnables(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+        position: Position,
+        background: bool,
+    ) -> Result<(), LspcError> {
+        self.sync_document(&text_document)?;
+        let choices = Rc::clone(&self.choices);
+        let handler = self.rust_analyzer_handler(lang_id, &text_document, "runnables")?;
+        let params = RunnablesParams {
+            text_document,
+            position: Some(position),
+        };
+        handler.lsp_request::<Runnables>(
+            params,
+            Box::new(move |editor: &mut E, _handler, response| {
+                match response.len() {
+                    0 => editor.message("No runnable at cursor")?,
+                    1 => run_runnable(editor, &response[0], background)?,
+                    _ => {
+                        let labels = response
+                            .iter()
+                            .map(|runnable| runnable.label.clone())
+                            .collect::<Vec<_>>();
+                        let choice = Choice::Runnable {
+                            runnables: response,
+                            background,
+                        };
+                        ask_choice(editor, &choices, "Select runnable:", &labels, choice)?;
+                    }
+                }
+
+                Ok(())
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    // Replace matches of `query` in `scope` once user has seen them
+    pub(super) fn request_ssr(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+        query: String,
+        position: Position,
+        scope: SsrScope,
+    ) -> Result<(), LspcError> {
+        self.sync_document(&text_document)?;
+        self.rust_analyzer_handler(lang_id, &text_document, "structural search and replace")?;
+        let selections = match scope {
+            SsrScope::File => match self.editor.line_count(&text_document)? {
+                Some(line_count) => {
+                    vec![Range::new(
+                        Position::new(0, 0),
+                        Position::new(line_count, 0),
+                    )]
+                }
+                None => return Ok(()),
+            },
+            SsrScope::Selection(range) => vec![range],
+            SsrScope::Workspace => Vec::new(),
+        };
+        let choices = Rc::clone(&self.choices);
+        let handler =
+            self.rust_analyzer_handler(lang_id, &text_document, "structural search and replace")?;
+        let title = format!("SSR {}", query);
+        let params = SsrParams {
+            query,
+            parse_only: false,
+            text_document,
+            position,
+            selections,
+        };
+        handler.lsp_request::<Ssr>(
+            params,
+            Box::new(move |editor: &mut E, _handler, response| {
+                let edit = AnnotatedWorkspaceEdit::from_value(response)
+                    .map_err(|e| EditorError::Failed(format!("{}", e)))?;
+                let no_changes = edit.edit.changes.as_ref().map_or(true, HashMap::is_empty)
+                    && edit.edit.document_changes.is_none();
+                if no_changes {
+                    editor.message("No match")?;
+                } else {
+                    confirm_workspace_edit(
+                        editor,
+                        &choices,
+                        &title,
+                        &edit,
+                        true,
+                        AfterEdit::Nothing,
+                    )?;
+                }
+
+                Ok(())
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    // Send `extension` for the selected `range`, its start for the
+    // ones which take a position
+    pub(super) fn request_extension(
+        &mut self,
+        lang_id: &str,
+        text_document: TextDocumentIdentifier,
+        extension: Extension,
+        range: Range,
+    ) -> Result<(), LspcError> {
+        self.sync_document(&text_document)?;
+        let handler = self.rust_analyzer_handler(lang_id, &text_document, extension.feature())?;
+        let position_params = TextDocumentPositionParams {
+            text_document: text_document.clone(),
+            position: range.start,
+        };
+
+        match extension {
+            Extension::ExpandMacro => {
+                handler.lsp_request::<ExpandMacro>(
+                    position_params,
+                    Box::new(|editor: &mut E, _handler, response| {
+                        let expanded = match response {
+                            Some(expanded) => expanded,
+                            None => {
+                                editor.message("No macro under cursor")?;
+                                return Ok(());
+                            }
+                        };
+                        let mut lines =
+                            vec![format!("// Recursive expansion of {}!", expanded.name)];
+                        lines.extend(expanded.expansion.lines().map(String::from));
+                        editor.show_scratch("__LspcExpandMacro__", &lines, Some("rust"))?;
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Extension::ParentModule => {
+                handler.lsp_request::<ParentModule>(
+                    position_params,
+                    Box::new(|editor: &mut E, _handler, response| {
+                        let locations = response.map(definition_locations).unwrap_or_default();
+                        match locations.len() {
+                            0 => editor.message("No parent module")?,
+                            1 => editor.goto(&locations[0])?,
+                            _ => {
+                                let items = locations
+                                    .into_iter()
+                                    .map(|location| (String::new(), location))
+                                    .collect::<Vec<_>>();
+                                editor.show_symbols("Parent modules", &items)?;
+                            }
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Extension::JoinLines => {
+                let params = JoinLinesParams {
+                    text_document: text_document.clone(),
+                    ranges: vec![range],
+                };
+                handler.lsp_request::<JoinLines>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        let mut changes = HashMap::new();
+                        changes.insert(text_document.uri, response);
+                        editor.apply_workspace_edit(&WorkspaceEdit::new(changes))?;
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Extension::MatchingBrace => {
+                let params = MatchingBraceParams {
+                    text_document: text_document.clone(),
+                    positions: vec![range.start],
+                };
+                handler.lsp_request::<MatchingBrace>(
+                    params,
+                    Box::new(move |editor: &mut E, _handler, response| {
+                        if let Some(position) = response.into_iter().next() {
+                            let range = Range::new(position, position);
+                            editor.goto(&Location::new(text_document.uri, range))?;
+                        }
+
+                        Ok(())
+                    }),
+                )?;
+            }
+            Extension::SyntaxTree => {
+                let params = SyntaxTreeParams {
+                    text_document,
+                    range: if range.start == range.end {
+                        None
+                    } else {
+                        Some(range)
+                    },
+                };
+                handler.lsp_request::<SyntaxTree>(
+                    params,
+                    Box::new(|editor: &mut E, _handler, response| {
+                        show_text(editor, "__LspcSyntaxTree__", &response)
+                    }),
+                )?;
+            }
+            Extension::AnalyzerStatus => {
+                let params = AnalyzerStatusParams {
+                    text_document: Some(text_document),
+                };
+                handler.lsp_request::<AnalyzerStatus>(
+                    params,
+                    Box::new(|editor: &mut E, _handler, response| {
+                        show_text(editor, "__LspcAnalyzerStatus__", &response)
+                    }),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_runnable_command_line() {
+        let runnable: Runnable = serde_json::from_value(json!({
+            "label": "test tests::it_works",
+            "kind": "cargo",
+            "args": {
+                "workspaceRoot": "/abc",
+                "cargoArgs": ["test", "--package", "d", "--lib"],
+                "cargoExtraArgs": [],
+                "executableArgs": ["tests::it_works", "--exact"],
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            runnable.command_line(true),
+            vec![
+                "cargo",
+                "test",
+                "--package",
+                "d",
+                "--lib",
+                "--message-format=json",
+                "--",
+                "tests::it_works",
+                "--exact",
+            ]
+        );
+        assert_eq!(runnable.cwd(), Some("/abc"));
+
+        let runnable: Runnable = serde_json::from_value(json!({
+            "label": "test it_works",
+            "bin": "cargo",
+            "args": ["test", "--", "it_works"],
+            "env": { "RUST_BACKTRACE": "short" },
+            "cwd": null,
+        }))
+        .unwrap();
+        assert_eq!(
+            runnable.command_line(true),
+            vec!["cargo", "test", "--message-format=json", "--", "it_works"]
+        );
+        assert_eq!(runnable.env()["RUST_BACKTRACE"], "short");
+    }
+}
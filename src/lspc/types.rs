@@ -1,28 +1,823 @@
-use lsp_types::{request::Request, Range, TextDocumentIdentifier};
+use std::collections::{HashMap, HashSet};
+
+use lsp_types::{
+    notification::Notification, request::Request, CodeActionParams, DocumentChangeOperation,
+    DocumentChanges, DocumentLinkParams, Location, NumberOrString, Position, Range, RenameParams,
+    ResourceOp, SymbolKind, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit,
+    WorkspaceEdit,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use url::Url;
 
-pub enum InlayHints {}
+// `initialize` request with raw params and result, so capabilities
+// unknown to `lsp_types` can be sent and are not lost
+pub enum RawInitialize {}
 
-impl Request for InlayHints {
-    type Params = InlayHintsParams;
-    type Result = Vec<InlayHint>;
-    const METHOD: &'static str = "rust-analyzer/inlayHints";
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct InlayHintsParams {
-    pub text_document: TextDocumentIdentifier,
+impl Request for RawInitialize {
+    type Params = Value;
+    type Result = Value;
+    const METHOD: &'static str = "initialize";
 }
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum InlayKind {
     TypeHint,
+    ParameterHint,
+    ChainingHint,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct InlayHint {
     pub range: Range,
     pub kind: InlayKind,
     pub label: String,
 }
+
+pub enum InlayHintRequest {}
+
+impl Request for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Option<Vec<StandardInlayHint>>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+// Hint of `textDocument/inlayHint`, shown like the ones of rust-analyzer
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StandardInlayHint {
+    pub position: Position,
+    pub label: InlayHintLabel,
+    // 1 for types and 2 for parameters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum InlayHintLabel {
+    String(String),
+    LabelParts(Vec<InlayHintLabelPart>),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InlayHintLabelPart {
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+impl From<StandardInlayHint> for InlayHint {
+    fn from(hint: StandardInlayHint) -> Self {
+        let label = match hint.label {
+            InlayHintLabel::String(label) => label,
+            InlayHintLabel::LabelParts(parts) => {
+                parts.into_iter().map(|part| part.value).collect::<String>()
+            }
+        };
+        let kind = match hint.kind {
+            Some(2) => InlayKind::ParameterHint,
+            _ => InlayKind::TypeHint,
+        };
+
+        InlayHint {
+            range: Range::new(hint.position, hint.position),
+            kind,
+            label,
+        }
+    }
+}
+
+// `workspace/inlayHint/refresh`, hints of all documents are outdated
+pub enum InlayHintRefresh {}
+
+impl Request for InlayHintRefresh {
+    type Params = ();
+    type Result = ();
+    const METHOD: &'static str = "workspace/inlayHint/refresh";
+}
+
+pub const SEMANTIC_TOKEN_TYPES: &[&str] = &[
+    "namespace",
+    "type",
+    "class",
+    "enum",
+    "interface",
+    "struct",
+    "typeParameter",
+    "parameter",
+    "variable",
+    "property",
+    "enumMember",
+    "event",
+    "function",
+    "method",
+    "macro",
+    "keyword",
+    "modifier",
+    "comment",
+    "string",
+    "number",
+    "regexp",
+    "operator",
+];
+
+pub const SEMANTIC_TOKEN_MODIFIERS: &[&str] = &[
+    "declaration",
+    "definition",
+    "readonly",
+    "static",
+    "deprecated",
+    "abstract",
+    "async",
+    "modification",
+    "documentation",
+    "defaultLibrary",
+];
+
+// `workspace/applyEdit` with the raw edit, to keep change annotations
+pub enum ApplyAnnotatedWorkspaceEdit {}
+
+impl Request for ApplyAnnotatedWorkspaceEdit {
+    type Params = ApplyAnnotatedWorkspaceEditParams;
+    type Result = ApplyWorkspaceEditResult;
+    const METHOD: &'static str = "workspace/applyEdit";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApplyAnnotatedWorkspaceEditParams {
+    pub label: Option<String>,
+    pub edit: Value,
+}
+
+// `ApplyWorkspaceEditResponse` with the reason of a failure
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyWorkspaceEditResult {
+    pub applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+}
+
+// A change of a workspace edit, changes are applied in order
+#[derive(Debug, PartialEq, Clone)]
+pub enum WorkspaceChange {
+    Edits(Url, Vec<TextEdit>),
+    Resource(ResourceOp),
+}
+
+// Changes of `edit`, `document_changes` is preferred over `changes`
+// if server provides both
+pub fn workspace_changes(edit: &WorkspaceEdit) -> Vec<WorkspaceChange> {
+    let mut changes = Vec::new();
+    if let Some(ref document_changes) = edit.document_changes {
+        match document_changes {
+            DocumentChanges::Edits(edits) => changes.extend(edits.iter().map(|edit| {
+                WorkspaceChange::Edits(edit.text_document.uri.clone(), edit.edits.clone())
+            })),
+            DocumentChanges::Operations(operations) => {
+                changes.extend(operations.iter().map(|operation| match operation {
+                    DocumentChangeOperation::Edit(edit) => {
+                        WorkspaceChange::Edits(edit.text_document.uri.clone(), edit.edits.clone())
+                    }
+                    DocumentChangeOperation::Op(op) => WorkspaceChange::Resource(op.clone()),
+                }))
+            }
+        }
+    } else if let Some(ref edits) = edit.changes {
+        changes.extend(
+            edits
+                .iter()
+                .map(|(uri, edits)| WorkspaceChange::Edits(uri.clone(), edits.clone())),
+        );
+    }
+
+    changes
+}
+
+// `textDocument/codeAction` with raw actions, to keep change annotations
+// of their edits
+pub enum RawCodeActions {}
+
+impl Request for RawCodeActions {
+    type Params = CodeActionParams;
+    type Result = Option<Vec<Value>>;
+    const METHOD: &'static str = "textDocument/codeAction";
+}
+
+// `textDocument/rename` with a raw edit, to keep its change annotations
+pub enum RawRename {}
+
+impl Request for RawRename {
+    type Params = RenameParams;
+    type Result = Option<Value>;
+    const METHOD: &'static str = "textDocument/rename";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeAnnotation {
+    pub label: String,
+    #[serde(default)]
+    pub needs_confirmation: bool,
+    pub description: Option<String>,
+}
+
+// A workspace edit with the annotations of its changes
+#[derive(Debug)]
+pub struct AnnotatedWorkspaceEdit {
+    pub edit: WorkspaceEdit,
+    pub annotations: Vec<ChangeAnnotation>,
+}
+
+impl AnnotatedWorkspaceEdit {
+    pub fn from_value(value: Value) -> Result<Self, serde_json::Error> {
+        let mut annotations: HashMap<String, ChangeAnnotation> =
+            match value.get("changeAnnotations") {
+                Some(annotations) => serde_json::from_value(annotations.clone())?,
+                None => HashMap::new(),
+            };
+        let mut ids = HashSet::new();
+        annotation_ids(&value, &mut ids);
+        let mut ids = ids.into_iter().collect::<Vec<_>>();
+        ids.sort();
+
+        Ok(AnnotatedWorkspaceEdit {
+            edit: serde_json::from_value(value)?,
+            annotations: ids
+                .into_iter()
+                .filter_map(|id| annotations.remove(&id))
+                .collect(),
+        })
+    }
+
+    // Whether user has to accept the edit before it is applied
+    pub fn needs_confirmation(&self) -> bool {
+        self.annotations
+            .iter()
+            .any(|annotation| annotation.needs_confirmation)
+    }
+}
+
+impl From<WorkspaceEdit> for AnnotatedWorkspaceEdit {
+    fn from(edit: WorkspaceEdit) -> Self {
+        AnnotatedWorkspaceEdit {
+            edit,
+            annotations: Vec::new(),
+        }
+    }
+}
+
+// Annotations referred to by text edits and file operations
+fn annotation_ids(value: &Value, ids: &mut HashSet<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    Value::String(id) if key == "annotationId" => {
+                        ids.insert(id.clone());
+                    }
+                    _ => annotation_ids(value, ids),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                annotation_ids(value, ids);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Client capabilities unknown to `lsp_types`
+pub fn client_capabilities() -> Value {
+    json!({
+        "textDocument": text_document_capabilities(),
+        "window": {
+            "workDoneProgress": true,
+        },
+        "workspace": {
+            "inlayHint": { "refreshSupport": true },
+            "workspaceEdit": {
+                "documentChanges": true,
+                "resourceOperations": ["create", "rename", "delete"],
+                "failureHandling": "abort",
+                "changeAnnotationSupport": { "groupsOnLabel": false },
+            },
+        },
+    })
+}
+
+fn text_document_capabilities() -> Value {
+    json!({
+        "semanticTokens": {
+            "requests": {
+                "range": true,
+                "full": { "delta": true },
+            },
+            "tokenTypes": SEMANTIC_TOKEN_TYPES,
+            "tokenModifiers": SEMANTIC_TOKEN_MODIFIERS,
+            "formats": ["relative"],
+        },
+        "callHierarchy": {
+            "dynamicRegistration": false,
+        },
+        "typeHierarchy": {
+            "dynamicRegistration": false,
+        },
+        "inlayHint": {
+            "dynamicRegistration": false,
+        },
+        "rename": {
+            "dynamicRegistration": false,
+        },
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensLegend {
+    pub token_types: Vec<String>,
+    pub token_modifiers: Vec<String>,
+}
+
+// A token decoded with the legend
+#[derive(Debug, PartialEq)]
+pub struct SemanticToken {
+    pub line: u64,
+    pub start_character: u64,
+    pub length: u64,
+    pub token_type: String,
+    pub token_modifiers: Vec<String>,
+}
+
+impl SemanticTokensLegend {
+    // Each token is 5 integers: line and start relative to the previous
+    // token, length, type index and modifiers bit set
+    pub fn decode(&self, data: &[u64]) -> Vec<SemanticToken> {
+        let mut tokens = Vec::new();
+        let (mut line, mut start_character) = (0, 0);
+        for token in data.chunks_exact(5) {
+            if token[0] > 0 {
+                line += token[0];
+                start_character = 0;
+            }
+            start_character += token[1];
+
+            let token_type = match self.token_types.get(token[3] as usize) {
+                Some(token_type) => token_type.clone(),
+                None => continue,
+            };
+            let token_modifiers = self
+                .token_modifiers
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| token[4] & (1 << bit) != 0)
+                .map(|(_, modifier)| modifier.clone())
+                .collect();
+            tokens.push(SemanticToken {
+                line,
+                start_character,
+                length: token[2],
+                token_type,
+                token_modifiers,
+            });
+        }
+
+        tokens
+    }
+}
+
+// `semanticTokensProvider` server capability
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensOptions {
+    pub legend: SemanticTokensLegend,
+    // `true` or an empty object
+    #[serde(default)]
+    pub range: Option<Value>,
+    // `true` or `{ "delta": true }`
+    #[serde(default)]
+    pub full: Option<Value>,
+}
+
+impl SemanticTokensOptions {
+    pub fn supports_range(&self) -> bool {
+        match self.range {
+            Some(Value::Bool(supported)) => supported,
+            Some(Value::Object(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn supports_full(&self) -> bool {
+        match self.full {
+            Some(Value::Bool(supported)) => supported,
+            Some(Value::Object(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn supports_delta(&self) -> bool {
+        match self.full {
+            Some(ref full) => full.get("delta") == Some(&Value::Bool(true)),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokens {
+    pub result_id: Option<String>,
+    pub data: Vec<u64>,
+}
+
+impl SemanticTokens {
+    pub fn apply_edits(&mut self, mut edits: Vec<SemanticTokensEdit>) {
+        // Edits refer to the previous data, apply from the end
+        edits.sort_by_key(|edit| edit.start);
+        for edit in edits.into_iter().rev() {
+            let start = (edit.start as usize).min(self.data.len());
+            let end = (start + edit.delete_count as usize).min(self.data.len());
+            self.data.splice(start..end, edit.data.unwrap_or_default());
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensEdit {
+    pub start: u64,
+    pub delete_count: u64,
+    pub data: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDelta {
+    pub result_id: Option<String>,
+    pub edits: Vec<SemanticTokensEdit>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SemanticTokensFullDeltaResult {
+    Tokens(SemanticTokens),
+    Delta(SemanticTokensDelta),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDeltaParams {
+    pub text_document: TextDocumentIdentifier,
+    pub previous_result_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensRangeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+pub enum SemanticTokensFull {}
+
+impl Request for SemanticTokensFull {
+    type Params = SemanticTokensParams;
+    type Result = Option<SemanticTokens>;
+    const METHOD: &'static str = "textDocument/semanticTokens/full";
+}
+
+pub enum SemanticTokensFullDelta {}
+
+impl Request for SemanticTokensFullDelta {
+    type Params = SemanticTokensDeltaParams;
+    type Result = Option<SemanticTokensFullDeltaResult>;
+    const METHOD: &'static str = "textDocument/semanticTokens/full/delta";
+}
+
+pub enum SemanticTokensRange {}
+
+impl Request for SemanticTokensRange {
+    type Params = SemanticTokensRangeParams;
+    type Result = Option<SemanticTokens>;
+    const METHOD: &'static str = "textDocument/semanticTokens/range";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub detail: Option<String>,
+    pub uri: Url,
+    pub range: Range,
+    pub selection_range: Range,
+    // Kept by the server between requests
+    pub data: Option<Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyIncomingCall {
+    pub from: CallHierarchyItem,
+    // Calls in `from`
+    pub from_ranges: Vec<Range>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyOutgoingCall {
+    pub to: CallHierarchyItem,
+    // Calls in the requested item
+    pub from_ranges: Vec<Range>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CallHierarchyCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+pub enum CallHierarchyPrepare {}
+
+impl Request for CallHierarchyPrepare {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Vec<CallHierarchyItem>>;
+    const METHOD: &'static str = "textDocument/prepareCallHierarchy";
+}
+
+pub enum CallHierarchyIncomingCalls {}
+
+impl Request for CallHierarchyIncomingCalls {
+    type Params = CallHierarchyCallsParams;
+    type Result = Option<Vec<CallHierarchyIncomingCall>>;
+    const METHOD: &'static str = "callHierarchy/incomingCalls";
+}
+
+pub enum CallHierarchyOutgoingCalls {}
+
+impl Request for CallHierarchyOutgoingCalls {
+    type Params = CallHierarchyCallsParams;
+    type Result = Option<Vec<CallHierarchyOutgoingCall>>;
+    const METHOD: &'static str = "callHierarchy/outgoingCalls";
+}
+
+// Type hierarchy items have the same fields
+pub type TypeHierarchyItem = CallHierarchyItem;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TypeHierarchyParams {
+    pub item: TypeHierarchyItem,
+}
+
+pub enum TypeHierarchyPrepare {}
+
+impl Request for TypeHierarchyPrepare {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "textDocument/prepareTypeHierarchy";
+}
+
+pub enum TypeHierarchySupertypes {}
+
+impl Request for TypeHierarchySupertypes {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/supertypes";
+}
+
+pub enum TypeHierarchySubtypes {}
+
+impl Request for TypeHierarchySubtypes {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/subtypes";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WorkDoneProgressCreateParams {
+    pub token: NumberOrString,
+}
+
+pub enum WorkDoneProgressCreate {}
+
+impl Request for WorkDoneProgressCreate {
+    type Params = WorkDoneProgressCreateParams;
+    type Result = ();
+    const METHOD: &'static str = "window/workDoneProgress/create";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProgressParams {
+    pub token: NumberOrString,
+    // `WorkDoneProgress` or partial results
+    pub value: Value,
+}
+
+pub enum Progress {}
+
+impl Notification for Progress {
+    type Params = ProgressParams;
+    const METHOD: &'static str = "$/progress";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum WorkDoneProgress {
+    Begin {
+        title: String,
+        message: Option<String>,
+        percentage: Option<u64>,
+    },
+    Report {
+        message: Option<String>,
+        percentage: Option<u64>,
+    },
+    End {
+        message: Option<String>,
+    },
+}
+
+// Some servers only send `target` once the link is resolved
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DocumentLink {
+    pub range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<String>,
+    // Kept by the server until the link is resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+pub enum DocumentLinks {}
+
+impl Request for DocumentLinks {
+    type Params = DocumentLinkParams;
+    type Result = Option<Vec<DocumentLink>>;
+    const METHOD: &'static str = "textDocument/documentLink";
+}
+
+pub enum DocumentLinkResolve {}
+
+impl Request for DocumentLinkResolve {
+    type Params = DocumentLink;
+    type Result = DocumentLink;
+    const METHOD: &'static str = "documentLink/resolve";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotated_workspace_edit() {
+        let edit = AnnotatedWorkspaceEdit::from_value(json!({
+            "changes": {
+                "file:///abc/d.rs": [{
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": 0, "character": 1 },
+                    },
+                    "newText": "b",
+                    "annotationId": "rename",
+                }],
+            },
+            "changeAnnotations": {
+                "rename": { "label": "Rename a", "needsConfirmation": true },
+                "unused": { "label": "Unused" },
+            },
+        }))
+        .unwrap();
+        let expected = vec![ChangeAnnotation {
+            label: String::from("Rename a"),
+            needs_confirmation: true,
+            description: None,
+        }];
+
+        assert_eq!(edit.annotations, expected);
+        assert!(edit.needs_confirmation());
+        assert_eq!(edit.edit.changes.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_workspace_changes_in_order() {
+        let edit: WorkspaceEdit = serde_json::from_value(json!({
+            "documentChanges": [
+                {
+                    "textDocument": { "uri": "file:///abc/a.rs", "version": 1 },
+                    "edits": [],
+                },
+                {
+                    "kind": "rename",
+                    "oldUri": "file:///abc/a.rs",
+                    "newUri": "file:///abc/b.rs",
+                },
+            ],
+        }))
+        .unwrap();
+        let changes = workspace_changes(&edit);
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            changes[0],
+            WorkspaceChange::Edits(Url::parse("file:///abc/a.rs").unwrap(), Vec::new())
+        );
+        match changes[1] {
+            WorkspaceChange::Resource(ResourceOp::Rename(ref rename)) => {
+                assert_eq!(rename.new_uri.path(), "/abc/b.rs")
+            }
+            ref change => panic!("unexpected change {:?}", change),
+        }
+    }
+
+    #[test]
+    fn test_standard_inlay_hint() {
+        let hint: StandardInlayHint = serde_json::from_value(json!({
+            "position": { "line": 2, "character": 9 },
+            "label": [{ "value": ": " }, { "value": "Vec<u8>" }],
+            "kind": 1,
+        }))
+        .unwrap();
+        let position = Position::new(2, 9);
+        let expected = InlayHint {
+            range: Range::new(position, position),
+            kind: InlayKind::TypeHint,
+            label: String::from(": Vec<u8>"),
+        };
+        assert_eq!(InlayHint::from(hint), expected);
+
+        let hint: StandardInlayHint = serde_json::from_value(json!({
+            "position": { "line": 2, "character": 9 },
+            "label": "count:",
+            "kind": 2,
+        }))
+        .unwrap();
+        assert_eq!(InlayHint::from(hint).kind, InlayKind::ParameterHint);
+    }
+
+    #[test]
+    fn test_decode_semantic_tokens() {
+        let legend = SemanticTokensLegend {
+            token_types: vec!["function".into(), "variable".into()],
+            token_modifiers: vec!["declaration".into(), "readonly".into()],
+        };
+        let data = vec![1, 3, 4, 0, 1, 0, 5, 2, 1, 3, 2, 1, 1, 0, 0];
+        let token =
+            |line, start_character, length, token_type: &str, modifiers: &[&str]| SemanticToken {
+                line,
+                start_character,
+                length,
+                token_type: token_type.into(),
+                token_modifiers: modifiers.iter().map(|m| m.to_string()).collect(),
+            };
+
+        assert_eq!(
+            legend.decode(&data),
+            vec![
+                token(1, 3, 4, "function", &["declaration"]),
+                token(1, 8, 2, "variable", &["declaration", "readonly"]),
+                token(3, 1, 1, "function", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_semantic_tokens_edits() {
+        let mut tokens = SemanticTokens {
+            result_id: None,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+        };
+        let edits = vec![
+            SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: None,
+            },
+            SemanticTokensEdit {
+                start: 0,
+                delete_count: 1,
+                data: Some(vec![0, 0]),
+            },
+        ];
+        tokens.apply_edits(edits);
+
+        assert_eq!(tokens.data, vec![0, 0, 2, 3, 4, 5]);
+    }
+}
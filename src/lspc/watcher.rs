@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crossbeam::channel::{self, Receiver};
+use lsp_types::{
+    DidChangeWatchedFilesParams, FileChangeType, FileEvent, FileSystemWatcher, WatchKind,
+};
+use url::Url;
+
+pub const WATCH_POLL_MS: u64 = 1000;
+pub const WATCH_DEBOUNCE_MS: u64 = 300;
+
+// Directories that are never scanned, they are either VCS internals or
+// build outputs that are too big to poll.
+const IGNORED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+type Snapshot = HashMap<PathBuf, (SystemTime, u64)>;
+
+// Watch files under the roots of the server by polling, and batch the
+// changes matching the `FileSystemWatcher`s registered by the server.
+pub struct FileWatcher {
+    root_paths: Vec<PathBuf>,
+    // registration id -> watchers
+    registrations: HashMap<String, Vec<FileSystemWatcher>>,
+    // One per root, empty if polling threads are not started
+    receivers: Vec<Receiver<Vec<(PathBuf, FileChangeType)>>>,
+    stop: Arc<AtomicBool>,
+    pending: Vec<FileEvent>,
+    scheduled_at: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn new(root_path: &str) -> Self {
+        FileWatcher {
+            root_paths: vec![PathBuf::from(root_path)],
+            registrations: HashMap::new(),
+            receivers: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            pending: Vec::new(),
+            scheduled_at: None,
+        }
+    }
+
+    pub fn register(&mut self, id: String, watchers: Vec<FileSystemWatcher>) {
+        self.registrations.insert(id, watchers);
+
+        if self.receivers.is_empty() {
+            for root_path in self.root_paths.clone() {
+                self.spawn_poller(root_path);
+            }
+        }
+    }
+
+    // Watch a workspace folder added to the server, unless it is
+    // already under a watched root
+    pub fn add_root(&mut self, root_path: PathBuf) {
+        if self.root_of(&root_path).is_some() {
+            return;
+        }
+
+        self.root_paths.push(root_path.clone());
+        if !self.receivers.is_empty() {
+            self.spawn_poller(root_path);
+        }
+    }
+
+    fn spawn_poller(&mut self, root_path: PathBuf) {
+        self.receivers.push(spawn_poller(
+            root_path,
+            Duration::from_millis(WATCH_POLL_MS),
+            Arc::clone(&self.stop),
+        ));
+    }
+
+    // Most specific watched root of `path`
+    fn root_of(&self, path: &Path) -> Option<&Path> {
+        self.root_paths
+            .iter()
+            .filter(|root_path| path.starts_with(root_path))
+            .max_by_key(|root_path| root_path.components().count())
+            .map(PathBuf::as_path)
+    }
+
+    pub fn unregister(&mut self, id: &str) {
+        self.registrations.remove(id);
+    }
+
+    // Collect changes reported by the polling thread, returns the batch
+    // once no new change came in for `WATCH_DEBOUNCE_MS`
+    pub fn poll(&mut self, now: Instant) -> Option<DidChangeWatchedFilesParams> {
+        let mut changes = Vec::new();
+        for receiver in &self.receivers {
+            while let Ok(batch) = receiver.try_recv() {
+                changes.extend(batch);
+            }
+        }
+
+        for (path, typ) in changes {
+            if !self.is_watched(&path, typ) {
+                continue;
+            }
+            if let Ok(uri) = Url::from_file_path(&path) {
+                self.add_pending(uri, typ);
+                self.scheduled_at = Some(now + Duration::from_millis(WATCH_DEBOUNCE_MS));
+            }
+        }
+
+        match self.scheduled_at {
+            Some(instant) if instant <= now => {
+                self.scheduled_at = None;
+                // Files created then deleted leave nothing to send
+                if self.pending.is_empty() {
+                    return None;
+                }
+                let changes = std::mem::replace(&mut self.pending, Vec::new());
+                Some(DidChangeWatchedFilesParams { changes })
+            }
+            _ => None,
+        }
+    }
+
+    // Merge `typ` with the pending change of `uri`, so that the batch
+    // tells what happened to the file since the last one
+    fn add_pending(&mut self, uri: Url, typ: FileChangeType) {
+        let index = match self.pending.iter().position(|event| event.uri == uri) {
+            Some(index) => index,
+            None => return self.pending.push(FileEvent { uri, typ }),
+        };
+
+        match merge_change_types(self.pending[index].typ, typ) {
+            Some(typ) => self.pending[index].typ = typ,
+            None => {
+                self.pending.remove(index);
+            }
+        }
+    }
+
+    fn is_watched(&self, path: &Path, typ: FileChangeType) -> bool {
+        let kind = match typ {
+            FileChangeType::Created => WatchKind::Create,
+            FileChangeType::Changed => WatchKind::Change,
+            FileChangeType::Deleted => WatchKind::Delete,
+        };
+        let relative_path = self
+            .root_of(path)
+            .and_then(|root_path| path.strip_prefix(root_path).ok())
+            .unwrap_or(path);
+
+        self.registrations
+            .values()
+            .flatten()
+            .filter(|watcher| watcher.kind.unwrap_or(WatchKind::all()).contains(kind))
+            .any(|watcher| {
+                let pattern = &watcher.glob_pattern;
+                if pattern.starts_with('/') {
+                    glob_match(pattern, path)
+                } else {
+                    glob_match(pattern, relative_path)
+                }
+            })
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// Change of a file which had change `previous` then `next`,
+// None if the server never has to know about it
+fn merge_change_types(previous: FileChangeType, next: FileChangeType) -> Option<FileChangeType> {
+    match (previous, next) {
+        (FileChangeType::Created, FileChangeType::Deleted) => None,
+        (FileChangeType::Created, _) => Some(FileChangeType::Created),
+        (_, FileChangeType::Deleted) => Some(FileChangeType::Deleted),
+        _ => Some(FileChangeType::Changed),
+    }
+}
+
+fn spawn_poller(
+    root_path: PathBuf,
+    interval: Duration,
+    stop: Arc<AtomicBool>,
+) -> Receiver<Vec<(PathBuf, FileChangeType)>> {
+    let (sender, receiver) = channel::unbounded();
+    thread::spawn(move || {
+        let mut snapshot = scan(&root_path);
+        while !stop.load(Ordering::Relaxed) {
+            thread::sleep(interval);
+
+            let current = scan(&root_path);
+            let changes = diff(&snapshot, &current);
+            snapshot = current;
+
+            if !changes.is_empty() && sender.send(changes).is_err() {
+                break;
+            }
+        }
+        log::debug!("Stop watching {:?}", root_path);
+    });
+
+    receiver
+}
+
+fn scan(root_path: &Path) -> Snapshot {
+    let mut snapshot = HashMap::new();
+    let mut dirs = vec![root_path.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::debug!("Cannot read dir {:?}: {}", dir, e);
+                continue;
+            }
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                let ignored = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| IGNORED_DIRS.contains(&name));
+                if !ignored {
+                    dirs.push(path);
+                }
+            } else if let Ok(modified) = metadata.modified() {
+                snapshot.insert(path, (modified, metadata.len()));
+            }
+        }
+    }
+
+    snapshot
+}
+
+fn diff(old: &Snapshot, new: &Snapshot) -> Vec<(PathBuf, FileChangeType)> {
+    let mut changes = Vec::new();
+    for (path, stat) in new.iter() {
+        match old.get(path) {
+            None => changes.push((path.clone(), FileChangeType::Created)),
+            Some(old_stat) if old_stat != stat => {
+                changes.push((path.clone(), FileChangeType::Changed))
+            }
+            _ => {}
+        }
+    }
+    for path in old.keys() {
+        if !new.contains_key(path) {
+            changes.push((path.clone(), FileChangeType::Deleted));
+        }
+    }
+
+    changes
+}
+
+// Match `path` against a LSP glob pattern, supports `*`, `?`, `**`,
+// `{a,b}` and `[a-z]` (but not nested braces)
+pub fn glob_match(pattern: &str, path: &Path) -> bool {
+    let path = match path.to_str() {
+        Some(path) => path,
+        None => return false,
+    };
+    let path_segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    expand_braces(pattern).iter().any(|pattern| {
+        let pattern_segments = pattern
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        match_segments(&pattern_segments, &path_segments)
+    })
+}
+
+fn expand_braces(pattern: &str) -> Vec<String> {
+    if let Some(start) = pattern.find('{') {
+        if let Some(len) = pattern[start..].find('}') {
+            let end = start + len;
+            let prefix = &pattern[..start];
+            let suffix = &pattern[end + 1..];
+            return pattern[start + 1..end]
+                .split(',')
+                .flat_map(|alt| expand_braces(&format!("{}{}{}", prefix, alt, suffix)))
+                .collect();
+        }
+    }
+
+    vec![pattern.to_owned()]
+}
+
+fn match_segments(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment.as_slice() == ['*', '*'] => {
+            (0..=path.len()).any(|i| match_segments(rest, &path[i..]))
+        }
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                match_segment(segment, name) && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| match_segment(rest, &name[i..])),
+        Some(('?', rest)) => !name.is_empty() && match_segment(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let close = match rest.iter().position(|c| *c == ']') {
+                Some(close) => close,
+                None => return name.first() == Some(&'[') && match_segment(rest, &name[1..]),
+            };
+            let c = match name.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+            let (negated, class) = match rest[..close].split_first() {
+                Some(('!', class)) => (true, class),
+                _ => (false, &rest[..close]),
+            };
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    matched |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+
+            matched != negated && match_segment(&rest[close + 1..], &name[1..])
+        }
+        Some((c, rest)) => name.first() == Some(c) && match_segment(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("**/*.rs", Path::new("src/lspc/handler.rs")));
+        assert!(glob_match("**/*.rs", Path::new("main.rs")));
+        assert!(glob_match(
+            "**/Cargo.{toml,lock}",
+            Path::new("a/Cargo.lock")
+        ));
+        assert!(glob_match("src/?.rs", Path::new("src/a.rs")));
+        assert!(glob_match("src/[a-c].rs", Path::new("src/b.rs")));
+        assert!(glob_match("/abc/**", Path::new("/abc/d/e.rs")));
+
+        assert!(!glob_match("**/*.rs", Path::new("src/lib.rsx")));
+        assert!(!glob_match("src/*.rs", Path::new("src/lspc/handler.rs")));
+        assert!(!glob_match("src/[!a-c].rs", Path::new("src/b.rs")));
+        assert!(!glob_match("**/Cargo.toml", Path::new("Cargo.lock")));
+    }
+
+    #[test]
+    fn test_scan_and_diff() {
+        let root = std::env::temp_dir().join(format!("lspc-watcher-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]").unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        fs::write(root.join("target/out.rs"), "").unwrap();
+
+        let before = scan(&root);
+        assert_eq!(2, before.len());
+
+        fs::write(root.join("Cargo.toml"), "[package]\nname = \"a\"").unwrap();
+        fs::remove_file(root.join("src/lib.rs")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+
+        let after = scan(&root);
+        let mut changes = diff(&before, &after);
+        changes.sort_by_key(|(path, _)| path.clone());
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            vec![
+                (root.join("Cargo.toml"), FileChangeType::Changed),
+                (root.join("src/lib.rs"), FileChangeType::Deleted),
+                (root.join("src/main.rs"), FileChangeType::Created),
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_poll_batches_watched_changes() {
+        let mut watcher = FileWatcher::new("/abc");
+        watcher.registrations.insert(
+            String::from("1"),
+            vec![FileSystemWatcher {
+                glob_pattern: String::from("**/*.rs"),
+                kind: None,
+            }],
+        );
+        let (sender, receiver) = channel::unbounded();
+        watcher.receivers.push(receiver);
+
+        sender
+            .send(vec![
+                (PathBuf::from("/abc/src/a.rs"), FileChangeType::Created),
+                (PathBuf::from("/abc/README.md"), FileChangeType::Changed),
+            ])
+            .unwrap();
+        let now = Instant::now();
+        assert!(watcher.poll(now).is_none());
+
+        sender
+            .send(vec![(
+                PathBuf::from("/abc/src/a.rs"),
+                FileChangeType::Changed,
+            )])
+            .unwrap();
+        assert!(watcher
+            .poll(now + Duration::from_millis(WATCH_DEBOUNCE_MS))
+            .is_none());
+
+        let params = watcher
+            .poll(now + Duration::from_millis(WATCH_DEBOUNCE_MS * 3))
+            .unwrap();
+        assert_eq!(
+            vec![FileEvent {
+                uri: Url::from_file_path("/abc/src/a.rs").unwrap(),
+                typ: FileChangeType::Created,
+            }],
+            params.changes
+        );
+    }
+
+    #[test]
+    fn test_added_roots() {
+        let mut watcher = FileWatcher::new("/abc");
+        watcher.add_root(PathBuf::from("/abc/sub"));
+        watcher.add_root(PathBuf::from("/def"));
+        assert_eq!(
+            vec![PathBuf::from("/abc"), PathBuf::from("/def")],
+            watcher.root_paths
+        );
+
+        // Patterns are relative to the root of each file
+        watcher.registrations.insert(
+            String::from("1"),
+            vec![FileSystemWatcher {
+                glob_pattern: String::from("src/*.rs"),
+                kind: None,
+            }],
+        );
+        assert!(watcher.is_watched(Path::new("/def/src/a.rs"), FileChangeType::Changed));
+        assert!(!watcher.is_watched(Path::new("/ghi/src/a.rs"), FileChangeType::Changed));
+    }
+
+    #[test]
+    fn test_merge_change_types() {
+        use FileChangeType::*;
+
+        assert_eq!(Some(Created), merge_change_types(Created, Changed));
+        assert_eq!(None, merge_change_types(Created, Deleted));
+        assert_eq!(Some(Changed), merge_change_types(Deleted, Created));
+        assert_eq!(Some(Deleted), merge_change_types(Changed, Deleted));
+        assert_eq!(Some(Changed), merge_change_types(Changed, Changed));
+    }
+}
@@ -1,8 +1,9 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     error::Error,
-    fmt,
-    io::{BufRead, Write},
+    fmt, fs,
+    io::{self, BufRead, Write},
+    path::Path,
     sync::atomic::{AtomicU64, Ordering},
     thread::{self, JoinHandle},
     time::Duration,
@@ -11,9 +12,13 @@ use std::{
 use crossbeam::channel::{self, Receiver, Sender};
 
 use lsp_types::{
-    self as lsp, GotoCapability, Hover, HoverCapability, HoverContents, Location, MarkedString,
-    MarkupContent, MarkupKind, Position, ShowMessageParams, TextDocumentClientCapabilities,
-    TextDocumentIdentifier, TextEdit,
+    self as lsp, CodeLens, Color, ColorInformation, Diagnostic, DiagnosticSeverity,
+    DocumentHighlight, DocumentHighlightKind, DocumentSymbolCapability, Documentation,
+    FoldingRange, FoldingRangeCapability, FoldingRangeKind, GenericCapability, GotoCapability,
+    Hover, HoverCapability, HoverContents, Location, LogMessageParams, MarkedString, MarkupContent,
+    MarkupKind, MessageType, ParameterLabel, Position, Range, ResourceOp, ShowMessageParams,
+    SignatureHelp, SignatureHelpCapability, SignatureInformation, SignatureInformationSettings,
+    TextDocumentClientCapabilities, TextDocumentIdentifier, TextEdit, WorkspaceClientCapabilities,
 };
 use rmpv::{
     decode::read_value,
@@ -29,7 +34,15 @@ use serde::{
 };
 use url::Url;
 
-use crate::lspc::{types::InlayHint, BufferId, Editor, EditorError, Event, LsConfig};
+use crate::lspc::{
+    diff::{unified_diff, PREVIEW_CONTEXT_LINES},
+    rust_analyzer::{Extension, SsrScope},
+    types::{
+        AnnotatedWorkspaceEdit, InlayHint, InlayKind, SemanticToken, WorkDoneProgress,
+        WorkspaceChange,
+    },
+    BufferId, Editor, EditorError, Event, LsConfig, TreeItem,
+};
 use crate::rpc::{self, Message, RpcError};
 
 pub struct Neovim {
@@ -110,12 +123,170 @@ impl ToDisplay for Hover {
     }
 }
 
+impl ToDisplay for Documentation {
+    fn to_display(&self) -> Vec<String> {
+        match self {
+            Documentation::String(ref s) => s.to_display(),
+            Documentation::MarkupContent(ref mc) => mc.to_display(),
+        }
+    }
+
+    fn vim_filetype(&self) -> Option<String> {
+        match self {
+            Documentation::String(_) => Some("text".to_string()),
+            Documentation::MarkupContent(ref mc) => mc.vim_filetype(),
+        }
+    }
+}
+
 impl ToDisplay for str {
     fn to_display(&self) -> Vec<String> {
         self.lines().map(String::from).collect()
     }
 }
 
+// Byte range of the active parameter in the signature label
+fn active_parameter_range(
+    signature: &SignatureInformation,
+    active_parameter: usize,
+) -> Option<(usize, usize)> {
+    let parameter = signature.parameters.as_ref()?.get(active_parameter)?;
+    match parameter.label {
+        ParameterLabel::Simple(ref name) => signature
+            .label
+            .find(name.as_str())
+            .map(|start| (start, start + name.len())),
+        ParameterLabel::LabelOffsets([start, end]) => Some((
+            utf16_to_byte_offset(&signature.label, start as usize),
+            utf16_to_byte_offset(&signature.label, end as usize),
+        )),
+    }
+}
+
+fn utf16_to_byte_offset(s: &str, offset: usize) -> usize {
+    let mut utf16_offset = 0;
+    for (byte_offset, c) in s.char_indices() {
+        if utf16_offset >= offset {
+            return byte_offset;
+        }
+        utf16_offset += c.len_utf16();
+    }
+
+    s.len()
+}
+
+// 'foldexpr' value of each line, up to the last folded one
+fn fold_levels(ranges: &[FoldingRange]) -> Vec<String> {
+    let line_count = ranges
+        .iter()
+        .map(|range| range.end_line as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut levels = vec![0; line_count];
+    let mut starts = vec![false; line_count];
+    for range in ranges {
+        let (start, end) = (range.start_line as usize, range.end_line as usize);
+        if start >= end {
+            continue;
+        }
+        starts[start] = true;
+        for level in &mut levels[start..=end] {
+            *level += 1;
+        }
+    }
+
+    // `>N` starts a new fold even if previous one has the same level
+    levels
+        .into_iter()
+        .zip(starts)
+        .map(|(level, start)| {
+            if start {
+                format!(">{}", level)
+            } else {
+                level.to_string()
+            }
+        })
+        .collect()
+}
+
+fn run_params(
+    name: &str,
+    command: &[String],
+    cwd: Option<&str>,
+    env: &HashMap<String, String>,
+) -> Value {
+    let command = command
+        .iter()
+        .map(|arg| Value::from(arg.as_str()))
+        .collect::<Vec<_>>();
+    let env = env
+        .iter()
+        .map(|(key, value)| (Value::from(key.as_str()), Value::from(value.as_str())))
+        .collect::<Vec<_>>();
+
+    vec![
+        name.into(),
+        command.into(),
+        cwd.map_or(Value::Nil, Value::from),
+        Value::Map(env),
+    ]
+    .into()
+}
+
+// Arguments typed as JSON, a single value is the only argument
+fn command_arguments(json: &str) -> Result<Vec<serde_json::Value>, EditorError> {
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    match serde_json::from_str(json) {
+        Ok(serde_json::Value::Array(arguments)) => Ok(arguments),
+        Ok(argument) => Ok(vec![argument]),
+        Err(_) => Err(EditorError::Parse("command arguments are not valid JSON")),
+    }
+}
+
+// `#rrggbb` of a color, alpha is ignored
+fn color_hex(color: &Color) -> String {
+    let component = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        component(color.red),
+        component(color.green),
+        component(color.blue)
+    )
+}
+
+fn message_highlight(typ: MessageType) -> Value {
+    match typ {
+        MessageType::Error => "ErrorMsg".into(),
+        MessageType::Warning => "WarningMsg".into(),
+        _ => Value::Nil,
+    }
+}
+
+fn tree_items(items: &[TreeItem]) -> Result<Value, EditorError> {
+    let mut values = Vec::new();
+    for item in items {
+        let location = match item.location {
+            Some(ref location) => Value::Map(vec![
+                ("filename".into(), to_file_path(&location.uri)?.into()),
+                ("lnum".into(), (location.range.start.line + 1).into()),
+                ("col".into(), (location.range.start.character + 1).into()),
+            ]),
+            None => Value::Nil,
+        };
+        values.push(Value::Map(vec![
+            ("id".into(), item.id.into()),
+            ("text".into(), item.text.as_str().into()),
+            ("location".into(), location),
+            ("expandable".into(), item.expandable.into()),
+        ]));
+    }
+
+    Ok(values.into())
+}
+
 fn apply_edits(lines: &Vec<String>, edits: &Vec<TextEdit>) -> String {
     let mut sorted_edits = edits.clone();
     let mut editted_content = lines.join("\n");
@@ -141,6 +312,60 @@ fn apply_edits(lines: &Vec<String>, edits: &Vec<TextEdit>) -> String {
     editted_content
 }
 
+// Preview of a file operation, like the extended headers of git diffs
+fn resource_op_lines(op: &ResourceOp) -> Result<Vec<String>, EditorError> {
+    let lines = match op {
+        ResourceOp::Create(create) => vec![format!("new file {}", to_file_path(&create.uri)?)],
+        ResourceOp::Rename(rename) => vec![
+            format!("rename from {}", to_file_path(&rename.old_uri)?),
+            format!("rename to {}", to_file_path(&rename.new_uri)?),
+        ],
+        ResourceOp::Delete(delete) => {
+            vec![format!("deleted file {}", to_file_path(&delete.uri)?)]
+        }
+    };
+
+    Ok(lines)
+}
+
+fn io_error(path: &str, e: io::Error) -> EditorError {
+    EditorError::Failed(format!("{}: {}", path, e))
+}
+
+// Error unless the file at `path` can be replaced, Ok(false) if the
+// operation is to be skipped
+fn check_overwrite(
+    path: &str,
+    overwrite: Option<bool>,
+    ignore_if_exists: Option<bool>,
+) -> Result<bool, EditorError> {
+    if !Path::new(path).exists() || overwrite == Some(true) {
+        Ok(true)
+    } else if ignore_if_exists == Some(true) {
+        Ok(false)
+    } else {
+        Err(EditorError::Failed(format!("{} already exists", path)))
+    }
+}
+
+fn create_parent_dir(path: &str) -> Result<(), EditorError> {
+    match Path::new(path).parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(|e| io_error(path, e)),
+        None => Ok(()),
+    }
+}
+
+fn to_file_path(uri: &Url) -> Result<String, EditorError> {
+    let filepath = uri
+        .to_file_path()
+        .map_err(|_| EditorError::CommandDataInvalid("Location URI is not file path"))?;
+    let filepath = filepath
+        .to_str()
+        .ok_or(EditorError::CommandDataInvalid("Filepath is not UTF-8"))?;
+
+    Ok(filepath.to_owned())
+}
+
 fn to_document_offset(lines: &Vec<String>, pos: Position) -> usize {
     lines[..pos.line as usize]
         .iter()
@@ -196,6 +421,44 @@ fn to_event(msg: NvimMessage) -> Result<Event<BufferHandler>, EditorError> {
                     text_document: hover_params.1,
                     position: hover_params.2,
                 })
+            } else if method == "signature_help" {
+                #[derive(Deserialize)]
+                struct SignatureHelpParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                );
+
+                let signature_help_params: SignatureHelpParams = Deserialize::deserialize(params)
+                    .map_err(|_e| {
+                    EditorError::Parse("failed to parse signature help params")
+                })?;
+
+                Ok(Event::SignatureHelp {
+                    lang_id: signature_help_params.0,
+                    text_document: signature_help_params.1,
+                    position: signature_help_params.2,
+                })
+            } else if method == "document_highlight" {
+                #[derive(Deserialize)]
+                struct DocumentHighlightParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                );
+
+                let document_highlight_params: DocumentHighlightParams =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse document highlight params")
+                    })?;
+
+                Ok(Event::DocumentHighlight {
+                    lang_id: document_highlight_params.0,
+                    text_document: document_highlight_params.1,
+                    position: document_highlight_params.2,
+                })
             } else if method == "goto_definition" {
                 #[derive(Deserialize)]
                 struct GotoDefinitionParams(
@@ -228,6 +491,80 @@ fn to_event(msg: NvimMessage) -> Result<Event<BufferHandler>, EditorError> {
                     lang_id: inlay_hints_params.0,
                     text_document: inlay_hints_params.1,
                 })
+            } else if method == "capabilities" {
+                #[derive(Deserialize)]
+                struct CapabilitiesParams(String);
+
+                let capabilities_params: CapabilitiesParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse capabilities params"))?;
+
+                Ok(Event::Capabilities {
+                    lang_id: capabilities_params.0,
+                })
+            } else if method == "did_change_configuration" {
+                #[derive(Deserialize)]
+                struct DidChangeConfigurationParams(String, LsConfig);
+
+                let configuration_params: DidChangeConfigurationParams =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse did change configuration params")
+                    })?;
+
+                Ok(Event::DidChangeConfiguration {
+                    lang_id: configuration_params.0,
+                    config: configuration_params.1,
+                })
+            } else if method == "document_symbol" {
+                #[derive(Deserialize)]
+                struct DocumentSymbolParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                );
+
+                let document_symbol_params: DocumentSymbolParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse document symbol params"))?;
+
+                Ok(Event::DocumentSymbol {
+                    lang_id: document_symbol_params.0,
+                    text_document: document_symbol_params.1,
+                })
+            } else if method == "workspace_symbol" {
+                #[derive(Deserialize)]
+                struct WorkspaceSymbolParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    String,
+                );
+
+                let workspace_symbol_params: WorkspaceSymbolParams =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse workspace symbol params")
+                    })?;
+
+                Ok(Event::WorkspaceSymbol {
+                    lang_id: workspace_symbol_params.0,
+                    text_document: workspace_symbol_params.1,
+                    query: workspace_symbol_params.2,
+                })
+            } else if method == "code_action" {
+                #[derive(Deserialize)]
+                struct CodeActionParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Range,
+                );
+
+                let code_action_params: CodeActionParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse code action params"))?;
+
+                Ok(Event::CodeAction {
+                    lang_id: code_action_params.0,
+                    text_document: code_action_params.1,
+                    range: code_action_params.2,
+                })
             } else if method == "format_doc" {
                 #[derive(Deserialize)]
                 struct FormatDocParams(
@@ -245,119 +582,522 @@ fn to_event(msg: NvimMessage) -> Result<Event<BufferHandler>, EditorError> {
                     text_document: format_doc_params.1,
                     text_document_lines: format_doc_params.2,
                 })
-            } else if method == "did_open" {
+            } else if method == "format_range" {
                 #[derive(Deserialize)]
-                struct DidOpenParams(
-                    i64,
+                struct FormatRangeParams(
+                    String,
                     #[serde(deserialize_with = "text_document_from_path_str")]
                     TextDocumentIdentifier,
+                    Vec<String>,
+                    Range,
                 );
-                let did_open_params: DidOpenParams = Deserialize::deserialize(params)
-                    .map_err(|_e| EditorError::Parse("failed to parse did_open params"))?;
 
-                let text_document = did_open_params.1;
-                let buf_id = BufferHandler(did_open_params.0);
+                let format_range_params: FormatRangeParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse format range params"))?;
 
-                Ok(Event::DidOpen {
-                    buf_id,
-                    text_document,
+                Ok(Event::FormatRange {
+                    lang_id: format_range_params.0,
+                    text_document: format_range_params.1,
+                    text_document_lines: format_range_params.2,
+                    range: format_range_params.3,
                 })
+            } else if method == "format_on_type" {
+                #[derive(Deserialize)]
+                struct FormatOnTypeParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Vec<String>,
+                    Position,
+                    String,
+                );
 
-            // Callback messages
-            } else if method == "nvim_buf_lines_event" {
+                let format_on_type_params: FormatOnTypeParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse format on type params"))?;
+
+                Ok(Event::FormatOnType {
+                    lang_id: format_on_type_params.0,
+                    text_document: format_on_type_params.1,
+                    text_document_lines: format_on_type_params.2,
+                    position: format_on_type_params.3,
+                    ch: format_on_type_params.4,
+                })
+            } else if method == "code_lens" {
                 #[derive(Deserialize)]
-                struct NvimBufLinesEvent(
-                    NvimHandle,                      // bufnr
-                    Option<i64>,                     // changedtick
-                    i64,                             // firstline
-                    i64,                             // lastline
-                    Vec<String>,                     // linedata
-                    #[serde(default)] Option<Value>, // { more }
+                struct CodeLensParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
                 );
-                let buf_line_event: NvimBufLinesEvent =
+
+                let code_lens_params: CodeLensParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse code lens params"))?;
+
+                Ok(Event::CodeLens {
+                    lang_id: code_lens_params.0,
+                    text_document: code_lens_params.1,
+                })
+            } else if method == "code_lens_action" {
+                #[derive(Deserialize)]
+                struct CodeLensActionParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                );
+
+                let code_lens_action_params: CodeLensActionParams =
                     Deserialize::deserialize(params).map_err(|_e| {
-                        EditorError::Parse("failed to parse nvim_buf_lines_event params")
+                        EditorError::Parse("failed to parse code lens action params")
                     })?;
 
-                if !(buf_line_event.0).is_buf() {
-                    return Err(EditorError::UnexpectedResponse("Expect buffer handler"));
-                }
-                if buf_line_event.1.is_none() {
-                    return Err(EditorError::UnexpectedResponse(
-                        "Not support null changedtick",
-                    ));
-                }
+                Ok(Event::CodeLensAction {
+                    lang_id: code_lens_action_params.0,
+                    text_document: code_lens_action_params.1,
+                    position: code_lens_action_params.2,
+                })
+            } else if method == "expand_selection" {
+                #[derive(Deserialize)]
+                struct ExpandSelectionParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Range,
+                );
 
-                let buf_handler = buf_line_event.0.unwrap_buf();
-                let version = buf_line_event.1.unwrap();
-                let content_change = lsp::TextDocumentContentChangeEvent {
-                    range: Some(lsp::Range {
-                        start: lsp::Position::new(buf_line_event.2 as u64, 0),
-                        end: lsp::Position::new(buf_line_event.3 as u64, 0),
-                    }),
-                    range_length: None,
-                    text: buf_line_event.4.join("\n"),
-                };
+                let expand_selection_params: ExpandSelectionParams =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse expand selection params")
+                    })?;
 
-                Ok(Event::DidChange {
-                    buf_id: buf_handler,
-                    version,
-                    content_change,
+                Ok(Event::ExpandSelection {
+                    lang_id: expand_selection_params.0,
+                    text_document: expand_selection_params.1,
+                    range: expand_selection_params.2,
                 })
-            } else if method == "nvim_buf_detach_event" {
+            } else if method == "call_hierarchy" {
                 #[derive(Deserialize)]
-                struct NvimBufDetachEvent((NvimHandle,));
+                struct CallHierarchyParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                    bool,
+                );
 
-                let buf_detach_event: NvimBufDetachEvent = Deserialize::deserialize(params)
+                let call_hierarchy_params: CallHierarchyParams = Deserialize::deserialize(params)
                     .map_err(|_e| {
-                        EditorError::Parse("failed to parse nvim_buf_detach_event params")
-                    })?;
+                    EditorError::Parse("failed to parse call hierarchy params")
+                })?;
+
+                Ok(Event::CallHierarchy {
+                    lang_id: call_hierarchy_params.0,
+                    text_document: call_hierarchy_params.1,
+                    position: call_hierarchy_params.2,
+                    incoming: call_hierarchy_params.3,
+                })
+            } else if method == "type_hierarchy" {
+                #[derive(Deserialize)]
+                struct TypeHierarchyParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                    bool,
+                    bool,
+                );
 
-                if !((buf_detach_event.0).0).is_buf() {
-                    return Err(EditorError::UnexpectedResponse("Expect buffer handler"));
-                }
-                let buf_handler = (buf_detach_event.0).0.unwrap_buf();
+                let type_hierarchy_params: TypeHierarchyParams = Deserialize::deserialize(params)
+                    .map_err(|_e| {
+                    EditorError::Parse("failed to parse type hierarchy params")
+                })?;
+
+                Ok(Event::TypeHierarchy {
+                    lang_id: type_hierarchy_params.0,
+                    text_document: type_hierarchy_params.1,
+                    position: type_hierarchy_params.2,
+                    supertypes: type_hierarchy_params.3,
+                    flat: type_hierarchy_params.4,
+                })
+            } else if method == "tree_expand" {
+                #[derive(Deserialize)]
+                struct TreeExpandParams(String, u64);
 
-                Ok(Event::DidClose {
-                    buf_id: buf_handler,
+                let tree_expand_params: TreeExpandParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse tree expand params"))?;
+
+                Ok(Event::TreeExpand {
+                    tree: tree_expand_params.0,
+                    node_id: tree_expand_params.1,
                 })
-            } else {
-                Err(EditorError::UnexpectedMessage(format!(
-                    "unexpected notification {:?} {:?}",
-                    method, params
-                )))
-            }
-        }
-        _ => Err(EditorError::UnexpectedMessage(format!("{:?}", msg))),
-    }
-}
+            } else if method == "selected" {
+                #[derive(Deserialize)]
+                struct SelectedParams(u64, Option<usize>);
 
-impl Neovim {
-    pub fn new(rpc_client: rpc::Client<NvimMessage>) -> Self {
-        let (event_sender, event_receiver) = channel::unbounded();
-        let (subscription_sender, subscription_receiver) =
-            channel::bounded::<(u64, Sender<NvimMessage>)>(16);
+                let selected_params: SelectedParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse selected params"))?;
 
-        let rpc_receiver = rpc_client.receiver.clone();
-        let thread = thread::spawn(move || {
-            let mut subscriptions = Vec::<(u64, Sender<NvimMessage>)>::new();
+                Ok(Event::Selected {
+                    id: selected_params.0,
+                    index: selected_params.1,
+                })
+            } else if method == "previewed" {
+                #[derive(Deserialize)]
+                struct PreviewedParams(u64, Option<Vec<usize>>);
 
-            for nvim_msg in rpc_receiver {
-                log::debug!("< Neovim: {:?}", nvim_msg);
-                if let NvimMessage::RpcResponse { msgid, .. } = nvim_msg {
-                    while let Ok(sub) = subscription_receiver.try_recv() {
-                        subscriptions.push(sub);
-                    }
-                    if let Some(index) = subscriptions.iter().position(|item| item.0 == msgid) {
-                        let sub = subscriptions.swap_remove(index);
-                        sub.1.send(nvim_msg).unwrap();
-                    } else {
-                        log::error!("Received non-requested response: {}", msgid);
-                    }
+                let previewed_params: PreviewedParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse previewed params"))?;
+
+                Ok(Event::Previewed {
+                    id: previewed_params.0,
+                    accepted: previewed_params.1,
+                })
+            } else if method == "rust_analyzer" {
+                #[derive(Deserialize)]
+                struct RustAnalyzerParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    String,
+                    Range,
+                );
+
+                let rust_analyzer_params: RustAnalyzerParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse rust-analyzer params"))?;
+
+                Ok(Event::RustAnalyzer {
+                    lang_id: rust_analyzer_params.0,
+                    text_document: rust_analyzer_params.1,
+                    extension: Extension::parse(&rust_analyzer_params.2)
+                        .ok_or(EditorError::Parse("unknown rust-analyzer extension"))?,
+                    range: rust_analyzer_params.3,
+                })
+            } else if method == "ssr" {
+                #[derive(Deserialize)]
+                struct SsrParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    String,
+                    String,
+                    Range,
+                );
+
+                let ssr_params: SsrParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse ssr params"))?;
+                let range = ssr_params.4;
+                let scope = match ssr_params.3.as_str() {
+                    "file" => SsrScope::File,
+                    "selection" => SsrScope::Selection(range),
+                    "workspace" => SsrScope::Workspace,
+                    _ => return Err(EditorError::Parse("unknown ssr scope")),
+                };
+
+                Ok(Event::Ssr {
+                    lang_id: ssr_params.0,
+                    text_document: ssr_params.1,
+                    query: ssr_params.2,
+                    position: range.start,
+                    scope,
+                })
+            } else if method == "runnables" {
+                #[derive(Deserialize)]
+                struct RunnablesParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                    bool,
+                );
+
+                let runnables_params: RunnablesParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse runnables params"))?;
+
+                Ok(Event::Runnables {
+                    lang_id: runnables_params.0,
+                    text_document: runnables_params.1,
+                    position: runnables_params.2,
+                    background: runnables_params.3,
+                })
+            } else if method == "execute_command" {
+                #[derive(Deserialize)]
+                struct ExecuteCommandParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    String,
+                    String,
+                );
+
+                let execute_command_params: ExecuteCommandParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse execute command params"))?;
+
+                Ok(Event::ExecuteCommand {
+                    lang_id: execute_command_params.0,
+                    text_document: execute_command_params.1,
+                    command: execute_command_params.2,
+                    arguments: command_arguments(&execute_command_params.3)?,
+                })
+            } else if method == "rename" {
+                #[derive(Deserialize)]
+                struct RenameParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                    String,
+                );
+
+                let rename_params: RenameParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse rename params"))?;
+
+                Ok(Event::Rename {
+                    lang_id: rename_params.0,
+                    text_document: rename_params.1,
+                    position: rename_params.2,
+                    new_name: rename_params.3,
+                })
+            } else if method == "open_link" {
+                #[derive(Deserialize)]
+                struct OpenLinkParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                );
+
+                let open_link_params: OpenLinkParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse open link params"))?;
+
+                Ok(Event::OpenLink {
+                    lang_id: open_link_params.0,
+                    text_document: open_link_params.1,
+                    position: open_link_params.2,
+                })
+            } else if method == "color_presentation" {
+                #[derive(Deserialize)]
+                struct ColorPresentationParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Position,
+                );
+
+                let color_presentation_params: ColorPresentationParams =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse color presentation params")
+                    })?;
+
+                Ok(Event::ColorPresentation {
+                    lang_id: color_presentation_params.0,
+                    text_document: color_presentation_params.1,
+                    position: color_presentation_params.2,
+                })
+            } else if method == "semantic_tokens" {
+                #[derive(Deserialize)]
+                struct SemanticTokensParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Range,
+                );
+
+                let semantic_tokens_params: SemanticTokensParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse semantic tokens params"))?;
+
+                Ok(Event::SemanticTokens {
+                    lang_id: semantic_tokens_params.0,
+                    text_document: semantic_tokens_params.1,
+                    range: semantic_tokens_params.2,
+                })
+            } else if method == "shrink_selection" {
+                #[derive(Deserialize)]
+                struct ShrinkSelectionParams(
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                    Range,
+                );
+
+                let shrink_selection_params: ShrinkSelectionParams =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse shrink selection params")
+                    })?;
+
+                Ok(Event::ShrinkSelection {
+                    text_document: shrink_selection_params.0,
+                    range: shrink_selection_params.1,
+                })
+            } else if method == "did_save" {
+                #[derive(Deserialize)]
+                struct DidSaveParams(i64);
+
+                let did_save_params: DidSaveParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse did_save params"))?;
+
+                Ok(Event::DidSave {
+                    buf_id: BufferHandler(did_save_params.0),
+                })
+            } else if method == "did_open" {
+                #[derive(Deserialize)]
+                struct DidOpenParams(
+                    String,
+                    i64,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                );
+                let did_open_params: DidOpenParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse did_open params"))?;
+
+                let text_document = did_open_params.2;
+                let buf_id = BufferHandler(did_open_params.1);
+
+                Ok(Event::DidOpen {
+                    lang_id: did_open_params.0,
+                    buf_id,
+                    text_document,
+                })
+
+            // Callback messages
+            } else if method == "nvim_buf_lines_event" {
+                #[derive(Deserialize)]
+                struct NvimBufLinesEvent(
+                    NvimHandle,                      // bufnr
+                    Option<i64>,                     // changedtick
+                    i64,                             // firstline
+                    i64,                             // lastline
+                    Vec<String>,                     // linedata
+                    #[serde(default)] Option<Value>, // { more }
+                );
+                let buf_line_event: NvimBufLinesEvent =
+                    Deserialize::deserialize(params).map_err(|_e| {
+                        EditorError::Parse("failed to parse nvim_buf_lines_event params")
+                    })?;
+
+                if !(buf_line_event.0).is_buf() {
+                    return Err(EditorError::UnexpectedResponse("Expect buffer handler"));
+                }
+                if buf_line_event.1.is_none() {
+                    return Err(EditorError::UnexpectedResponse(
+                        "Not support null changedtick",
+                    ));
+                }
+
+                let buf_handler = buf_line_event.0.unwrap_buf();
+                let version = buf_line_event.1.unwrap();
+                let content_change = lsp::TextDocumentContentChangeEvent {
+                    range: Some(lsp::Range {
+                        start: lsp::Position::new(buf_line_event.2 as u64, 0),
+                        end: lsp::Position::new(buf_line_event.3 as u64, 0),
+                    }),
+                    range_length: None,
+                    text: buf_line_event.4.join("\n"),
+                };
+
+                Ok(Event::DidChange {
+                    buf_id: buf_handler,
+                    version,
+                    content_change,
+                    line_count: buf_line_event.4.len() as u64,
+                })
+            } else if method == "nvim_buf_detach_event" {
+                #[derive(Deserialize)]
+                struct NvimBufDetachEvent((NvimHandle,));
+
+                let buf_detach_event: NvimBufDetachEvent = Deserialize::deserialize(params)
+                    .map_err(|_e| {
+                        EditorError::Parse("failed to parse nvim_buf_detach_event params")
+                    })?;
+
+                if !((buf_detach_event.0).0).is_buf() {
+                    return Err(EditorError::UnexpectedResponse("Expect buffer handler"));
+                }
+                let buf_handler = (buf_detach_event.0).0.unwrap_buf();
+
+                Ok(Event::DidClose {
+                    buf_id: buf_handler,
+                })
+            } else {
+                Err(EditorError::UnexpectedMessage(format!(
+                    "unexpected notification {:?} {:?}",
+                    method, params
+                )))
+            }
+        }
+        // Requests block Neovim until they are replied
+        NvimMessage::RpcRequest {
+            msgid,
+            method,
+            params,
+        } => {
+            if method == "format_on_save" {
+                #[derive(Deserialize)]
+                struct FormatOnSaveParams(
+                    String,
+                    #[serde(deserialize_with = "text_document_from_path_str")]
+                    TextDocumentIdentifier,
+                );
+
+                let format_on_save_params: FormatOnSaveParams = Deserialize::deserialize(params)
+                    .map_err(|_e| EditorError::Parse("failed to parse format on save params"))?;
+
+                Ok(Event::FormatOnSave {
+                    request_id: msgid,
+                    lang_id: format_on_save_params.0,
+                    text_document: format_on_save_params.1,
+                })
+            } else {
+                Err(EditorError::UnexpectedMessage(format!(
+                    "unexpected request {:?} {:?}",
+                    method, params
+                )))
+            }
+        }
+        _ => Err(EditorError::UnexpectedMessage(format!("{:?}", msg))),
+    }
+}
+
+impl Neovim {
+    pub fn new(rpc_client: rpc::Client<NvimMessage>) -> Self {
+        let (event_sender, event_receiver) = channel::unbounded();
+        let (subscription_sender, subscription_receiver) =
+            channel::bounded::<(u64, Sender<NvimMessage>)>(16);
+
+        let rpc_receiver = rpc_client.receiver.clone();
+        let rpc_sender = rpc_client.sender.clone();
+        let thread = thread::spawn(move || {
+            let mut subscriptions = Vec::<(u64, Sender<NvimMessage>)>::new();
+
+            for nvim_msg in rpc_receiver {
+                log::debug!("< Neovim: {:?}", nvim_msg);
+                if let NvimMessage::RpcResponse { msgid, .. } = nvim_msg {
+                    while let Ok(sub) = subscription_receiver.try_recv() {
+                        subscriptions.push(sub);
+                    }
+                    if let Some(index) = subscriptions.iter().position(|item| item.0 == msgid) {
+                        let sub = subscriptions.swap_remove(index);
+                        sub.1.send(nvim_msg).unwrap();
+                    } else {
+                        log::error!("Received non-requested response: {}", msgid);
+                    }
                 } else {
+                    let request_id = match nvim_msg {
+                        NvimMessage::RpcRequest { msgid, .. } => Some(msgid),
+                        _ => None,
+                    };
                     match to_event(nvim_msg) {
                         Ok(event) => event_sender.send(event).unwrap(),
-                        Err(e) => log::error!("Cannot convert nvim msg to editor event: {:?}", e),
+                        Err(e) => {
+                            log::error!("Cannot convert nvim msg to editor event: {:?}", e);
+                            // Neovim is blocked until the request is answered
+                            if let Some(msgid) = request_id {
+                                let response = NvimMessage::RpcResponse {
+                                    msgid,
+                                    error: Value::from(format!("{:?}", e)),
+                                    result: Value::Nil,
+                                };
+                                rpc_sender.send(response).unwrap();
+                            }
+                        }
                     }
                 }
             }
@@ -430,167 +1170,899 @@ impl Neovim {
         Ok(())
     }
 
-    pub fn command(&self, command: &str) -> Result<NvimMessage, EditorError> {
-        let params = vec![Value::from(command)].into();
-        self.request("nvim_command", params)
+    // Send request and return its result
+    fn request_result(&self, method: &str, params: Value) -> Result<Value, EditorError> {
+        match self.request(method, params)? {
+            NvimMessage::RpcResponse { result, error, .. } => {
+                if error.is_nil() {
+                    Ok(result)
+                } else {
+                    Err(EditorError::Failed(format!("{} failed: {}", method, error)))
+                }
+            }
+            _ => Err(EditorError::UnexpectedResponse("Expected response")),
+        }
+    }
+
+    pub fn command(&self, command: &str) -> Result<NvimMessage, EditorError> {
+        let params = vec![Value::from(command)].into();
+        self.request("nvim_command", params)
+    }
+
+    // Call VimL function
+    pub fn call_function(&self, func: &str, args: Value) -> Result<NvimMessage, EditorError> {
+        let params = vec![func.into(), args].into();
+        self.request("nvim_call_function", params)
+    }
+
+    // Call VimL function and return its result
+    pub fn eval_function(&self, func: &str, args: Value) -> Result<Value, EditorError> {
+        let params = vec![func.into(), args].into();
+        self.request_result("nvim_call_function", params)
+    }
+
+    // Number of the buffer loaded for `uri`, None if there is none
+    pub fn buffer_of(&self, uri: &Url) -> Result<Option<u64>, EditorError> {
+        let filepath = to_file_path(uri)?;
+        let bufnr = self
+            .eval_function("bufnr", vec![Value::from(filepath)].into())?
+            .as_i64()
+            .ok_or(EditorError::UnexpectedResponse("Expected buffer number"))?;
+
+        if bufnr == -1 {
+            Ok(None)
+        } else {
+            Ok(Some(bufnr as u64))
+        }
+    }
+
+    // Buffer of `uri` and its lines, loading it if needed
+    fn buffer_lines(&self, uri: &Url) -> Result<(Value, Vec<String>), EditorError> {
+        let filepath = to_file_path(uri)?;
+        let buffer_id = self.eval_function("bufadd", vec![Value::from(filepath)].into())?;
+        self.eval_function("bufload", vec![buffer_id.clone()].into())?;
+
+        let lines = self.request_result(
+            "nvim_buf_get_lines",
+            vec![buffer_id.clone(), 0.into(), (-1).into(), false.into()].into(),
+        )?;
+        let lines: Vec<String> = from_value(lines)
+            .map_err(|_| EditorError::UnexpectedResponse("Expected buffer lines"))?;
+
+        Ok((buffer_id, lines))
+    }
+
+    // Create, rename or delete a file, and rename or wipe out its buffer
+    pub fn apply_resource_op(&self, op: &ResourceOp) -> Result<(), EditorError> {
+        match op {
+            ResourceOp::Create(create) => {
+                let path = to_file_path(&create.uri)?;
+                let options = create.options.as_ref();
+                let overwrite = options.and_then(|options| options.overwrite);
+                let ignore_if_exists = options.and_then(|options| options.ignore_if_exists);
+                if check_overwrite(&path, overwrite, ignore_if_exists)? {
+                    create_parent_dir(&path)?;
+                    fs::write(&path, "").map_err(|e| io_error(&path, e))?;
+                }
+            }
+            ResourceOp::Rename(rename) => {
+                let old_path = to_file_path(&rename.old_uri)?;
+                let new_path = to_file_path(&rename.new_uri)?;
+                let options = rename.options.as_ref();
+                let overwrite = options.and_then(|options| options.overwrite);
+                let ignore_if_exists = options.and_then(|options| options.ignore_if_exists);
+                if !check_overwrite(&new_path, overwrite, ignore_if_exists)? {
+                    return Ok(());
+                }
+                create_parent_dir(&new_path)?;
+                fs::rename(&old_path, &new_path).map_err(|e| io_error(&old_path, e))?;
+                // Edits already applied to the buffer are kept
+                if let Some(bufnr) = self.buffer_of(&rename.old_uri)? {
+                    self.request_result(
+                        "nvim_buf_set_name",
+                        vec![Value::from(bufnr), Value::from(new_path)].into(),
+                    )?;
+                }
+            }
+            ResourceOp::Delete(delete) => {
+                let path = to_file_path(&delete.uri)?;
+                let options = delete.options.as_ref();
+                let recursive = options.and_then(|options| options.recursive) == Some(true);
+                let ignore_if_not_exists =
+                    options.and_then(|options| options.ignore_if_not_exists) == Some(true);
+                let file_path = Path::new(&path);
+                let result = if !file_path.exists() {
+                    if ignore_if_not_exists {
+                        return Ok(());
+                    }
+                    Err(io::Error::from(io::ErrorKind::NotFound))
+                } else if !file_path.is_dir() {
+                    fs::remove_file(&path)
+                } else if recursive {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_dir(&path)
+                };
+                result.map_err(|e| io_error(&path, e))?;
+                if let Some(bufnr) = self.buffer_of(&delete.uri)? {
+                    self.command(&format!("silent! bwipeout! {}", bufnr))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Apply `edits` to the buffer of `uri`, loading it if needed
+    pub fn apply_buffer_edits(&self, uri: &Url, edits: &Vec<TextEdit>) -> Result<(), EditorError> {
+        let (buffer_id, lines) = self.buffer_lines(uri)?;
+        let editted_content = apply_edits(&lines, edits);
+        let new_lines = editted_content.split('\n').map(Value::from).collect();
+        self.request_result(
+            "nvim_buf_set_lines",
+            vec![
+                buffer_id,
+                0.into(),
+                (-1).into(),
+                false.into(),
+                Value::Array(new_lines),
+            ]
+            .into(),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn create_namespace(&self, ns_name: &str) -> Result<u64, EditorError> {
+        let params = vec![Value::from(ns_name)].into();
+        let response = self.request("nvim_create_namespace", params)?;
+        log::debug!("Create namespace response: {:?}", response);
+        if let NvimMessage::RpcResponse { ref result, .. } = response {
+            Ok(result.as_u64().ok_or(EditorError::UnexpectedResponse(
+                "Expected nvim_create_namespace respsonse",
+            ))?)
+        } else {
+            Err(EditorError::UnexpectedResponse(
+                "Expected nvim_create_namespace respsonse",
+            ))
+        }
+    }
+
+    pub fn set_virtual_text(
+        &self,
+        buffer_id: u64,
+        ns_id: u64,
+        line: u64,
+        chunks: Vec<(&str, &str)>,
+    ) -> Result<(), EditorError> {
+        let chunks = chunks
+            .into_iter()
+            .map(|(label, hl_group)| Value::Array(vec![Value::from(label), Value::from(hl_group)]))
+            .collect::<Vec<_>>()
+            .into();
+        self.notify(
+            "nvim_buf_set_virtual_text",
+            &vec![
+                buffer_id.into(),
+                ns_id.into(),
+                line.into(),
+                chunks,
+                Value::Map(Vec::new()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    // Highlight `range` with `hl_group`, line by line
+    pub fn add_highlight(
+        &self,
+        buffer_id: u64,
+        ns_id: u64,
+        hl_group: &str,
+        range: &Range,
+    ) -> Result<(), EditorError> {
+        for line in range.start.line..=range.end.line {
+            let col_start = if line == range.start.line {
+                range.start.character as i64
+            } else {
+                0
+            };
+            let col_end = if line == range.end.line {
+                range.end.character as i64
+            } else {
+                -1
+            };
+            self.notify(
+                "nvim_buf_add_highlight",
+                &vec![
+                    buffer_id.into(),
+                    ns_id.into(),
+                    hl_group.into(),
+                    line.into(),
+                    col_start.into(),
+                    col_end.into(),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear_namespace(&self, buffer_id: u64, ns_id: u64) -> Result<(), EditorError> {
+        self.notify(
+            "nvim_buf_clear_namespace",
+            &vec![buffer_id.into(), ns_id.into(), 0.into(), (-1).into()],
+        )
+    }
+
+    pub fn receiver(&self) -> &Receiver<NvimMessage> {
+        &self.rpc_client.receiver
+    }
+
+    pub fn close(self) {
+        self.thread.join().unwrap();
+    }
+}
+
+impl BufferId for BufferHandler {}
+
+impl Editor for Neovim {
+    type BufferId = BufferHandler;
+
+    fn events(&self) -> Receiver<Event<BufferHandler>> {
+        self.event_receiver.clone()
+    }
+
+    fn capabilities(&self) -> lsp_types::ClientCapabilities {
+        lsp_types::ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                did_change_watched_files: Some(GenericCapability {
+                    dynamic_registration: Some(true),
+                }),
+                workspace_folders: Some(true),
+                configuration: Some(true),
+                did_change_configuration: Some(GenericCapability {
+                    dynamic_registration: Some(false),
+                }),
+                apply_edit: Some(true),
+                execute_command: Some(GenericCapability {
+                    dynamic_registration: Some(false),
+                }),
+                ..Default::default()
+            }),
+            text_document: Some(TextDocumentClientCapabilities {
+                hover: Some(HoverCapability {
+                    dynamic_registration: None,
+                    content_format: Some(vec![MarkupKind::PlainText, MarkupKind::Markdown]),
+                }),
+                definition: Some(GotoCapability {
+                    dynamic_registration: None,
+                    link_support: None,
+                }),
+                document_symbol: Some(DocumentSymbolCapability {
+                    hierarchical_document_symbol_support: Some(true),
+                    ..Default::default()
+                }),
+                folding_range: Some(FoldingRangeCapability {
+                    dynamic_registration: None,
+                    range_limit: None,
+                    line_folding_only: Some(true),
+                }),
+                code_lens: Some(GenericCapability {
+                    dynamic_registration: None,
+                }),
+                selection_range: Some(GenericCapability {
+                    dynamic_registration: None,
+                }),
+                signature_help: Some(SignatureHelpCapability {
+                    dynamic_registration: None,
+                    signature_information: Some(SignatureInformationSettings {
+                        documentation_format: Some(vec![
+                            MarkupKind::PlainText,
+                            MarkupKind::Markdown,
+                        ]),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            window: None,
+            experimental: None,
+        }
+    }
+
+    fn say_hello(&self) -> Result<(), EditorError> {
+        let params = vec![Value::from("echo 'hello from the other side'")].into();
+        self.request("nvim_command", params)
+            .map_err(|_| EditorError::Timeout)?;
+
+        Ok(())
+    }
+
+    fn message(&mut self, msg: &str) -> Result<(), EditorError> {
+        self.call_function("lspc#command#echo", vec![msg.into(), Value::Nil].into())?;
+        Ok(())
+    }
+
+    fn show_hover(
+        &mut self,
+        _text_document: &TextDocumentIdentifier,
+        hover: &Hover,
+    ) -> Result<(), EditorError> {
+        // FIXME: check current buffer is `text_document`
+        let bufname = "__LanguageClient__";
+        let filetype = if let Some(ft) = &hover.vim_filetype() {
+            ft.as_str().into()
+        } else {
+            Value::Nil
+        };
+        let lines = hover
+            .to_display()
+            .iter()
+            .map(|item| Value::from(item.as_str()))
+            .collect::<Vec<_>>()
+            .into();
+        self.call_function(
+            "lspc#command#open_hover_preview",
+            vec![bufname.into(), lines, filetype].into(),
+        )?;
+
+        Ok(())
+    }
+
+    fn show_signature_help(
+        &mut self,
+        _text_document: &TextDocumentIdentifier,
+        signature_help: &SignatureHelp,
+    ) -> Result<(), EditorError> {
+        let active_signature = signature_help.active_signature.unwrap_or(0) as usize;
+        let signature = match signature_help
+            .signatures
+            .get(active_signature)
+            .or_else(|| signature_help.signatures.first())
+        {
+            Some(signature) => signature,
+            None => return Ok(()),
+        };
+
+        let mut lines = vec![Value::from(signature.label.as_str())];
+        let mut filetype = Value::Nil;
+        if let Some(ref documentation) = signature.documentation {
+            lines.push(Value::from(""));
+            lines.extend(documentation.to_display().into_iter().map(Value::from));
+            filetype = documentation.vim_filetype().map_or(Value::Nil, Value::from);
+        }
+
+        let active_parameter = signature_help.active_parameter.unwrap_or(0) as usize;
+        let active = active_parameter_range(signature, active_parameter)
+            .map_or(Value::Nil, |(start, end)| {
+                Value::from(vec![Value::from(start as u64), Value::from(end as u64)])
+            });
+        self.call_function(
+            "lspc#command#open_signature_help",
+            vec![lines.into(), filetype, active].into(),
+        )?;
+
+        Ok(())
+    }
+
+    fn set_trigger_characters(
+        &mut self,
+        lang_id: &str,
+        feature: &str,
+        chars: &[String],
+    ) -> Result<(), EditorError> {
+        let chars = chars
+            .iter()
+            .map(|c| Value::from(c.as_str()))
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#set_trigger_characters",
+            vec![lang_id.into(), feature.into(), Value::from(chars)].into(),
+        )?;
+
+        Ok(())
+    }
+
+    fn inline_hints(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        hints: &Vec<InlayHint>,
+    ) -> Result<(), EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        let ns_id = self.create_namespace(&format!("lspc_inlay_hints_{}", buffer_id))?;
+        self.clear_namespace(buffer_id, ns_id)?;
+
+        // Hints of a line are joined at its end
+        let mut chunks_by_line: BTreeMap<u64, Vec<(&str, &str)>> = BTreeMap::new();
+        for hint in hints {
+            let hl_group = match hint.kind {
+                InlayKind::TypeHint => "LspcTypeHint",
+                InlayKind::ParameterHint => "LspcParameterHint",
+                InlayKind::ChainingHint => "LspcChainingHint",
+            };
+            let chunk = (hint.label.as_str(), hl_group);
+            let chunks = chunks_by_line.entry(hint.range.start.line).or_default();
+            if !chunks.is_empty() {
+                chunks.push((" ", hl_group));
+            }
+            chunks.push(chunk);
+        }
+        for (line, chunks) in chunks_by_line {
+            self.set_virtual_text(buffer_id, ns_id, line, chunks)?;
+        }
+
+        Ok(())
+    }
+
+    fn line_count(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+    ) -> Result<Option<u64>, EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(None),
+        };
+        let line_count = self
+            .request_result("nvim_buf_line_count", vec![Value::from(buffer_id)].into())?
+            .as_u64()
+            .ok_or(EditorError::UnexpectedResponse("Expected line count"))?;
+
+        Ok(Some(line_count))
+    }
+
+    fn show_message(&mut self, params: &ShowMessageParams) -> Result<(), EditorError> {
+        let msg = format!("[LS-{:?}] {}", params.typ, params.message);
+        self.call_function(
+            "lspc#command#echo",
+            vec![msg.into(), message_highlight(params.typ)].into(),
+        )?;
+
+        Ok(())
+    }
+
+    fn log_message(&mut self, server: &str, params: &LogMessageParams) -> Result<(), EditorError> {
+        let bufname = format!("__LspcLog_{}__", server);
+        let lines = params
+            .message
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                if i == 0 {
+                    Value::from(format!("[{:?}] {}", params.typ, line))
+                } else {
+                    Value::from(line)
+                }
+            })
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#command#append_log",
+            vec![Value::from(bufname), lines.into()].into(),
+        )?;
+
+        Ok(())
+    }
+
+    fn show_diagnostics(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        diagnostics: &[Diagnostic],
+    ) -> Result<(), EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        let ns_id = self.create_namespace("lspc_diagnostics")?;
+        self.clear_namespace(buffer_id, ns_id)?;
+
+        // Only the first diagnostic of a line is shown
+        let mut last_line = None;
+        for diagnostic in diagnostics {
+            let line = diagnostic.range.start.line;
+            if last_line == Some(line) {
+                continue;
+            }
+            last_line = Some(line);
+
+            let hl_group = match diagnostic.severity {
+                Some(DiagnosticSeverity::Error) | None => "LspcError",
+                Some(DiagnosticSeverity::Warning) => "LspcWarning",
+                Some(DiagnosticSeverity::Information) => "LspcInformation",
+                Some(DiagnosticSeverity::Hint) => "LspcHint",
+            };
+            let label = format!(
+                "{}: {}",
+                diagnostic.source.as_ref().map_or("", String::as_str),
+                diagnostic.message.lines().next().unwrap_or("")
+            );
+            self.set_virtual_text(buffer_id, ns_id, line, vec![(&label, hl_group)])?;
+        }
+
+        Ok(())
+    }
+
+    fn show_document_highlights(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        highlights: &[DocumentHighlight],
+    ) -> Result<(), EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        // Same namespace is cleared by lspc#clear_document_highlight()
+        let ns_id = self.create_namespace(&format!("lspc_document_highlight_{}", buffer_id))?;
+        self.clear_namespace(buffer_id, ns_id)?;
+
+        for highlight in highlights {
+            let hl_group = match highlight.kind {
+                Some(DocumentHighlightKind::Read) => "LspcHighlightRead",
+                Some(DocumentHighlightKind::Write) => "LspcHighlightWrite",
+                Some(DocumentHighlightKind::Text) | None => "LspcHighlightText",
+            };
+            self.add_highlight(buffer_id, ns_id, hl_group, &highlight.range)?;
+        }
+
+        Ok(())
+    }
+
+    fn select(&mut self, id: u64, prompt: &str, items: &[String]) -> Result<(), EditorError> {
+        let items = items
+            .iter()
+            .map(|item| Value::from(item.as_str()))
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#select",
+            vec![Value::from(id), Value::from(prompt), Value::from(items)].into(),
+        )?;
+
+        Ok(())
     }
 
-    // Call VimL function
-    pub fn call_function(&self, func: &str, args: Value) -> Result<NvimMessage, EditorError> {
-        let params = vec![func.into(), args].into();
-        self.request("nvim_call_function", params)
+    fn show_scratch(
+        &mut self,
+        name: &str,
+        lines: &[String],
+        filetype: Option<&str>,
+    ) -> Result<(), EditorError> {
+        let filetype = filetype.map_or(Value::Nil, Value::from);
+        let lines = lines
+            .iter()
+            .map(|line| Value::from(line.as_str()))
+            .collect::<Vec<_>>()
+            .into();
+        self.call_function(
+            "lspc#command#open_scratch",
+            vec![name.into(), lines, filetype].into(),
+        )?;
+
+        Ok(())
     }
 
-    pub fn create_namespace(&self, ns_name: &str) -> Result<u64, EditorError> {
-        let params = vec![Value::from(ns_name)].into();
-        let response = self.request("nvim_create_namespace", params)?;
-        log::debug!("Create namespace response: {:?}", response);
-        if let NvimMessage::RpcResponse { ref result, .. } = response {
-            Ok(result.as_u64().ok_or(EditorError::UnexpectedResponse(
-                "Expected nvim_create_namespace respsonse",
-            ))?)
-        } else {
-            Err(EditorError::UnexpectedResponse(
-                "Expected nvim_create_namespace respsonse",
-            ))
+    fn show_code_lenses(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        lenses: &[CodeLens],
+    ) -> Result<(), EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        let ns_id = self.create_namespace("lspc_code_lens")?;
+        self.clear_namespace(buffer_id, ns_id)?;
+
+        // Lenses of a line are joined at its end
+        let mut titles_by_line: Vec<(u64, Vec<&str>)> = Vec::new();
+        for lens in lenses {
+            let title = match lens.command {
+                Some(ref command) => command.title.as_str(),
+                None => continue,
+            };
+            let line = lens.range.start.line;
+            match titles_by_line.iter_mut().find(|(l, _)| *l == line) {
+                Some((_, titles)) => titles.push(title),
+                None => titles_by_line.push((line, vec![title])),
+            }
+        }
+        for (line, titles) in titles_by_line {
+            let label = titles.join(" | ");
+            self.set_virtual_text(buffer_id, ns_id, line, vec![(&label, "LspcCodeLens")])?;
         }
+
+        Ok(())
     }
 
-    pub fn set_virtual_text(
-        &self,
-        buffer_id: u64,
-        ns_id: u64,
-        line: u64,
-        chunks: Vec<(&str, &str)>,
+    fn show_progress(
+        &mut self,
+        server: &str,
+        token: &str,
+        title: &str,
+        progress: &WorkDoneProgress,
     ) -> Result<(), EditorError> {
-        let chunks = chunks
-            .into_iter()
-            .map(|(label, hl_group)| Value::Array(vec![Value::from(label), Value::from(hl_group)]))
-            .collect::<Vec<_>>()
-            .into();
-        self.notify(
-            "nvim_buf_set_virtual_text",
-            &vec![
-                buffer_id.into(),
-                ns_id.into(),
-                line.into(),
-                chunks,
-                Value::Map(Vec::new()),
-            ],
+        let (kind, message, percentage) = match progress {
+            WorkDoneProgress::Begin {
+                message,
+                percentage,
+                ..
+            } => ("begin", message, *percentage),
+            WorkDoneProgress::Report {
+                message,
+                percentage,
+            } => ("report", message, *percentage),
+            WorkDoneProgress::End { message } => ("end", message, None),
+        };
+        self.call_function(
+            "lspc#progress#update",
+            vec![
+                server.into(),
+                token.into(),
+                kind.into(),
+                title.into(),
+                message.as_ref().map_or(Value::Nil, |m| m.as_str().into()),
+                percentage.map_or(Value::Nil, Value::from),
+            ]
+            .into(),
         )?;
 
         Ok(())
     }
 
-    pub fn receiver(&self) -> &Receiver<NvimMessage> {
-        &self.rpc_client.receiver
-    }
+    fn show_tree(&mut self, tree: &str, items: &[TreeItem]) -> Result<(), EditorError> {
+        self.call_function(
+            "lspc#tree#open",
+            vec![tree.into(), tree_items(items)?].into(),
+        )?;
 
-    pub fn close(self) {
-        self.thread.join().unwrap();
+        Ok(())
     }
-}
 
-impl BufferId for BufferHandler {}
+    fn show_tree_children(
+        &mut self,
+        tree: &str,
+        parent: u64,
+        items: &[TreeItem],
+    ) -> Result<(), EditorError> {
+        self.call_function(
+            "lspc#tree#add_children",
+            vec![tree.into(), parent.into(), tree_items(items)?].into(),
+        )?;
 
-impl Editor for Neovim {
-    type BufferId = BufferHandler;
+        Ok(())
+    }
 
-    fn events(&self) -> Receiver<Event<BufferHandler>> {
-        self.event_receiver.clone()
+    fn set_server_commands(
+        &mut self,
+        lang_id: &str,
+        commands: &[String],
+    ) -> Result<(), EditorError> {
+        let commands = commands
+            .iter()
+            .map(|command| Value::from(command.as_str()))
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#set_server_commands",
+            vec![lang_id.into(), Value::from(commands)].into(),
+        )?;
+
+        Ok(())
     }
 
-    fn capabilities(&self) -> lsp_types::ClientCapabilities {
-        lsp_types::ClientCapabilities {
-            workspace: None,
-            text_document: Some(TextDocumentClientCapabilities {
-                hover: Some(HoverCapability {
-                    dynamic_registration: None,
-                    content_format: Some(vec![MarkupKind::PlainText, MarkupKind::Markdown]),
-                }),
-                definition: Some(GotoCapability {
-                    dynamic_registration: None,
-                    link_support: None,
-                }),
-                ..Default::default()
-            }),
-            window: None,
-            experimental: None,
-        }
+    fn open_link(&mut self, target: &Url) -> Result<(), EditorError> {
+        let (target, is_file) = if target.scheme() == "file" {
+            (to_file_path(target)?, true)
+        } else {
+            (target.to_string(), false)
+        };
+        self.call_function(
+            "lspc#command#open_link",
+            vec![Value::from(target), Value::from(is_file)].into(),
+        )?;
+
+        Ok(())
     }
 
-    fn say_hello(&self) -> Result<(), EditorError> {
-        let params = vec![Value::from("echo 'hello from the other side'")].into();
-        self.request("nvim_command", params)
-            .map_err(|_| EditorError::Timeout)?;
+    fn show_document_colors(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        colors: &[ColorInformation],
+    ) -> Result<(), EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        let colors = colors
+            .iter()
+            .map(|color| {
+                Value::from(vec![
+                    Value::from(color.range.start.line),
+                    Value::from(color_hex(&color.color)),
+                ])
+            })
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#color#update",
+            vec![Value::from(buffer_id), Value::from(colors)].into(),
+        )?;
 
         Ok(())
     }
 
-    fn message(&mut self, msg: &str) -> Result<(), EditorError> {
-        self.command(&format!("echo '{}'", msg))?;
+    fn set_selection(
+        &mut self,
+        text_document: &TextDocumentIdentifier,
+        range: &Range,
+    ) -> Result<(), EditorError> {
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        let position = |position: &Position| {
+            Value::from(vec![
+                Value::from(position.line),
+                Value::from(position.character),
+            ])
+        };
+        self.call_function(
+            "lspc#command#set_selection",
+            vec![
+                Value::from(buffer_id),
+                position(&range.start),
+                position(&range.end),
+            ]
+            .into(),
+        )?;
+
         Ok(())
     }
 
-    fn show_hover(
+    fn show_semantic_tokens(
         &mut self,
-        _text_document: &TextDocumentIdentifier,
-        hover: &Hover,
+        text_document: &TextDocumentIdentifier,
+        range: Option<&Range>,
+        tokens: &[SemanticToken],
     ) -> Result<(), EditorError> {
-        // FIXME: check current buffer is `text_document`
-        let bufname = "__LanguageClient__";
-        let filetype = if let Some(ft) = &hover.vim_filetype() {
-            ft.as_str().into()
-        } else {
-            Value::Nil
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
         };
-        let lines = hover
-            .to_display()
+        // Cleared lines, the end is exclusive
+        let (start_line, end_line) = match range {
+            Some(range) if range.end.character > 0 => {
+                (range.start.line as i64, range.end.line as i64 + 1)
+            }
+            Some(range) => (range.start.line as i64, range.end.line as i64),
+            None => (0, -1),
+        };
+        let tokens = tokens
             .iter()
-            .map(|item| Value::from(item.as_str()))
-            .collect::<Vec<_>>()
-            .into();
+            .map(|token| {
+                Value::from(vec![
+                    Value::from(token.line),
+                    Value::from(token.start_character),
+                    Value::from(token.length),
+                    Value::from(token.token_type.as_str()),
+                    Value::from(
+                        token
+                            .token_modifiers
+                            .iter()
+                            .map(|modifier| Value::from(modifier.as_str()))
+                            .collect::<Vec<_>>(),
+                    ),
+                ])
+            })
+            .collect::<Vec<_>>();
         self.call_function(
-            "lspc#command#open_hover_preview",
-            vec![bufname.into(), lines, filetype].into(),
+            "lspc#semantic#update",
+            vec![
+                Value::from(buffer_id),
+                Value::from(start_line),
+                Value::from(end_line),
+                Value::from(tokens),
+            ]
+            .into(),
         )?;
 
         Ok(())
     }
 
-    fn inline_hints(
+    fn show_folding_ranges(
         &mut self,
         text_document: &TextDocumentIdentifier,
-        hints: &Vec<InlayHint>,
+        ranges: &[FoldingRange],
     ) -> Result<(), EditorError> {
-        // FIXME: check current buffer is `text_document`
-        let ns_id = self.create_namespace(text_document.uri.path())?;
-        for hint in hints {
-            self.set_virtual_text(
-                0,
-                ns_id,
-                hint.range.start.line,
-                vec![(&hint.label, "error")],
-            )?;
-        }
+        let buffer_id = match self.buffer_of(&text_document.uri)? {
+            Some(buffer_id) => buffer_id,
+            None => return Ok(()),
+        };
+        let levels = fold_levels(ranges)
+            .into_iter()
+            .map(Value::from)
+            .collect::<Vec<_>>();
+        let folds = ranges
+            .iter()
+            .map(|range| {
+                let kind = match range.kind {
+                    Some(FoldingRangeKind::Comment) => "comment",
+                    Some(FoldingRangeKind::Imports) => "imports",
+                    Some(FoldingRangeKind::Region) => "region",
+                    None => "",
+                };
+                Value::from(vec![Value::from(range.start_line + 1), Value::from(kind)])
+            })
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#fold#update",
+            vec![Value::from(buffer_id), levels.into(), folds.into()].into(),
+        )?;
 
         Ok(())
     }
 
-    fn show_message(&mut self, params: &ShowMessageParams) -> Result<(), EditorError> {
-        self.command(&format!("echo '[LS-{:?}] {}'", params.typ, params.message))?;
+    fn run_in_terminal(
+        &mut self,
+        name: &str,
+        command: &[String],
+        cwd: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<(), EditorError> {
+        let params = run_params(name, command, cwd, env);
+        self.call_function("lspc#command#run_in_terminal", params)?;
+
+        Ok(())
+    }
+
+    fn run_in_quickfix(
+        &mut self,
+        name: &str,
+        command: &[String],
+        cwd: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<(), EditorError> {
+        let params = run_params(name, command, cwd, env);
+        self.call_function("lspc#command#run_in_quickfix", params)?;
+
+        Ok(())
+    }
+
+    fn show_symbols(
+        &mut self,
+        title: &str,
+        symbols: &[(String, Location)],
+    ) -> Result<(), EditorError> {
+        if symbols.is_empty() {
+            return self.message("No symbols found");
+        }
+
+        let mut items = Vec::new();
+        for (text, location) in symbols {
+            let filename = to_file_path(&location.uri)?;
+            items.push(Value::Map(vec![
+                ("filename".into(), filename.into()),
+                ("lnum".into(), (location.range.start.line + 1).into()),
+                ("col".into(), (location.range.start.character + 1).into()),
+                ("text".into(), text.as_str().into()),
+            ]));
+        }
+        let what = Value::Map(vec![
+            ("title".into(), title.into()),
+            ("items".into(), items.into()),
+        ]);
+        self.call_function(
+            "setloclist",
+            vec![0.into(), Vec::<Value>::new().into(), "r".into(), what].into(),
+        )?;
+        self.command("lopen")?;
 
         Ok(())
     }
 
     fn goto(&mut self, location: &Location) -> Result<(), EditorError> {
-        let filepath = location
-            .uri
-            .to_file_path()
-            .map_err(|_| EditorError::CommandDataInvalid("Location URI is not file path"))?;
-        let filepath = filepath
-            .to_str()
-            .ok_or(EditorError::CommandDataInvalid("Filepath is not UTF-8"))?;
+        let filepath = to_file_path(&location.uri)?;
         self.command(&format!("edit {}", filepath))?;
         let line = location.range.start.line + 1;
         let col = location.range.start.character + 1;
@@ -619,6 +2091,88 @@ impl Editor for Neovim {
         Ok(())
     }
 
+    fn apply_workspace_changes(&mut self, changes: &[WorkspaceChange]) -> Result<(), EditorError> {
+        for change in changes {
+            match change {
+                WorkspaceChange::Edits(uri, edits) => self.apply_buffer_edits(uri, edits)?,
+                WorkspaceChange::Resource(op) => self.apply_resource_op(op)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn preview_workspace_edit(
+        &mut self,
+        id: u64,
+        title: &str,
+        edit: &AnnotatedWorkspaceEdit,
+        changes: &[WorkspaceChange],
+    ) -> Result<(), EditorError> {
+        let mut lines = edit
+            .annotations
+            .iter()
+            .map(|annotation| match annotation.description {
+                Some(ref description) => format!("# {}: {}", annotation.label, description),
+                None => format!("# {}", annotation.label),
+            })
+            .collect::<Vec<_>>();
+        // First line of each change, they can be skipped one by one. Hunks
+        // of a file follow each other, under the header of the first one
+        let mut starts = Vec::new();
+        let mut previous_uri = None;
+        for change in changes {
+            starts.push(Value::from(lines.len()));
+            let (uri, edits) = match change {
+                WorkspaceChange::Edits(uri, edits) => (uri, edits),
+                WorkspaceChange::Resource(op) => {
+                    lines.extend(resource_op_lines(op)?);
+                    previous_uri = None;
+                    continue;
+                }
+            };
+            if previous_uri != Some(uri) {
+                let path = to_file_path(uri)?;
+                lines.push(format!("--- {}", path));
+                lines.push(format!("+++ {}", path));
+                previous_uri = Some(uri);
+            }
+            let (_, old_lines) = self.buffer_lines(uri)?;
+            let new_lines = apply_edits(&old_lines, edits)
+                .split('\n')
+                .map(String::from)
+                .collect::<Vec<_>>();
+            lines.extend(unified_diff(&old_lines, &new_lines, PREVIEW_CONTEXT_LINES));
+        }
+        let lines = lines
+            .iter()
+            .map(|line| Value::from(line.as_str()))
+            .collect::<Vec<_>>();
+        self.call_function(
+            "lspc#preview#open",
+            vec![
+                Value::from(id),
+                Value::from(title),
+                Value::from(lines),
+                Value::from(starts),
+            ]
+            .into(),
+        )?;
+
+        Ok(())
+    }
+
+    fn reply(&mut self, request_id: u64, error: Option<&str>) -> Result<(), EditorError> {
+        let response = NvimMessage::RpcResponse {
+            msgid: request_id,
+            error: error.map_or(Value::Nil, Value::from),
+            result: Value::Nil,
+        };
+        self.rpc_client.sender.send(response).unwrap();
+
+        Ok(())
+    }
+
     fn watch_file_events(
         &mut self,
         _text_document: &TextDocumentIdentifier,
@@ -957,6 +2511,86 @@ mod tests {
         assert_eq!(editted_content, expected_content);
     }
 
+    #[test]
+    fn test_deserialize_rust_analyzer_params() {
+        let range = serde_json::json!({
+            "start": { "line": 1, "character": 2 },
+            "end": { "line": 3, "character": 0 },
+        });
+        let rust_analyzer_msg = NvimMessage::RpcNotification {
+            method: String::from("rust_analyzer"),
+            params: Value::from(vec![
+                Value::from("rust"),
+                Value::from("/abc/d.rs"),
+                Value::from("join_lines"),
+                to_value(&range).unwrap(),
+            ]),
+        };
+        let expected = Event::RustAnalyzer {
+            lang_id: String::from("rust"),
+            text_document: to_text_document("/abc/d.rs").unwrap(),
+            extension: Extension::JoinLines,
+            range: Range::new(Position::new(1, 2), Position::new(3, 0)),
+        };
+
+        assert_eq!(expected, to_event(rust_analyzer_msg).unwrap());
+    }
+
+    #[test]
+    fn test_command_arguments() {
+        assert_eq!(
+            command_arguments(" ").unwrap(),
+            Vec::<serde_json::Value>::new()
+        );
+        assert_eq!(
+            command_arguments(r#"[1, {"a": "b"}]"#).unwrap(),
+            vec![serde_json::json!(1), serde_json::json!({ "a": "b" })]
+        );
+        assert_eq!(
+            command_arguments(r#""a""#).unwrap(),
+            vec![serde_json::json!("a")]
+        );
+        assert!(command_arguments("[1,").is_err());
+    }
+
+    #[test]
+    fn test_color_hex() {
+        let color = Color {
+            red: 1.0,
+            green: 0.5,
+            blue: 0.0,
+            alpha: 1.0,
+        };
+
+        assert_eq!(color_hex(&color), "#ff8000");
+    }
+
+    #[test]
+    fn test_fold_levels() {
+        let fold = |start_line, end_line| FoldingRange {
+            start_line,
+            end_line,
+            ..Default::default()
+        };
+        let ranges = vec![fold(0, 2), fold(3, 8), fold(4, 5), fold(6, 7)];
+        let expected = vec![">1", "1", "1", ">1", ">2", "2", ">2", "2", "1"];
+
+        assert_eq!(fold_levels(&ranges), expected);
+    }
+
+    #[test]
+    fn test_active_parameter_range() {
+        let signature: SignatureInformation = serde_json::from_value(serde_json::json!({
+            "label": "fn add(a: i32, b: i32) -> i32",
+            "parameters": [{ "label": "a: i32" }, { "label": [15, 21] }],
+        }))
+        .unwrap();
+
+        assert_eq!(active_parameter_range(&signature, 0), Some((7, 13)));
+        assert_eq!(active_parameter_range(&signature, 1), Some((15, 21)));
+        assert_eq!(active_parameter_range(&signature, 2), None);
+    }
+
     #[test]
     fn test_deserialize_ls_config() {
         let value = Value::Map(vec![
@@ -970,14 +2604,28 @@ mod tests {
             ),
             (Value::from("indentation"), Value::from(4)),
             (Value::from("indentation_with_space"), Value::from(true)),
+            (Value::from("log_level"), Value::from("warning")),
+            (
+                Value::from("settings"),
+                Value::Map(vec![(
+                    Value::from("rust-analyzer"),
+                    Value::Map(vec![(Value::from("checkOnSave"), Value::from(false))]),
+                )]),
+            ),
         ]);
 
         let ls_config: LsConfig = Deserialize::deserialize(value).unwrap();
         let expected = LsConfig {
+            name: None,
             command: vec!["rustup".to_owned(), "run".to_owned()],
             root_markers: vec!["Cargo.lock".to_owned()],
             indentation: 4,
             indentation_with_space: true,
+            format_on_save: false,
+            preview_edits: false,
+            log_level: Some("warning".to_owned()),
+            initialization_options: None,
+            settings: Some(serde_json::json!({ "rust-analyzer": { "checkOnSave": false } })),
         };
 
         assert_eq!(expected, ls_config);
@@ -1007,10 +2655,16 @@ mod tests {
         let expected = Event::StartServer {
             lang_id: String::from("rust"),
             config: LsConfig {
+                name: None,
                 command: vec![String::from("rustup")],
                 root_markers: vec![String::from("Cargo.lock")],
                 indentation: 4,
                 indentation_with_space: true,
+                format_on_save: false,
+                preview_edits: false,
+                log_level: None,
+                initialization_options: None,
+                settings: None,
             },
             cur_path: String::from("/abc"),
         };
@@ -1037,6 +2691,26 @@ mod tests {
         assert_eq!(expected, to_event(inlay_hints_msg).unwrap());
     }
 
+    #[test]
+    fn test_deserialize_workspace_symbol_params() {
+        let workspace_symbol_msg = NvimMessage::RpcNotification {
+            method: String::from("workspace_symbol"),
+            params: Value::from(vec![
+                Value::from("rust"),
+                Value::from("/abc/d.rs"),
+                Value::from("Lspc"),
+            ]),
+        };
+        let text_document = to_text_document("/abc/d.rs").unwrap();
+        let expected = Event::WorkspaceSymbol {
+            lang_id: String::from("rust"),
+            text_document,
+            query: String::from("Lspc"),
+        };
+
+        assert_eq!(expected, to_event(workspace_symbol_msg).unwrap());
+    }
+
     #[test]
     fn test_deserialize_buffer_handler() {
         let v = Value::Ext(0, vec![13]);